@@ -0,0 +1,86 @@
+use criterion::{criterion_group, criterion_main, Criterion, ParameterizedBenchmark};
+use minecrust::{
+    block::BlockType,
+    chunk::CHUNK_SIDE_LEN,
+    chunk_map::ChunkMap,
+    geometry::{Aabb, Ray},
+    types::prelude::*,
+};
+
+struct Floor {
+    chunk_map: ChunkMap,
+}
+
+impl std::fmt::Debug for Floor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} chunks", self.chunk_map.len())
+    }
+}
+
+/// A floor of blocks filling the chunks at y = -1 within `radius` chunks of the origin. Its top is
+/// at y = -8.5.
+fn new_floor(radius: i32) -> Floor {
+    let mut chunk_map = ChunkMap::new(CHUNK_SIDE_LEN);
+    let half = (CHUNK_SIDE_LEN / 2) as i32;
+    let stone = BlockType::named("stone");
+    for cx in -radius..=radius {
+        for cz in -radius..=radius {
+            let center = chunk_map.chunk_center((cx, -1, cz));
+            let chunk = chunk_map.load_chunk((cx, -1, cz));
+            for x in -half..=half {
+                for y in -half..=half {
+                    for z in -half..=half {
+                        chunk.insert(
+                            &(center + Vector3f::new(x as f32, y as f32, z as f32)),
+                            stone,
+                        );
+                    }
+                }
+            }
+        }
+    }
+    Floor { chunk_map }
+}
+
+/// Tests `ray` against the AABB of every block in every chunk.
+fn intersect_aabbs(chunk_map: &ChunkMap, ray: &Ray) -> Option<(f32, Point3f)> {
+    chunk_map
+        .chunks()
+        .filter_map(|(_, chunk)| {
+            let blocks = chunk.blocks().map(|(pos, _)| pos).collect::<Vec<_>>();
+            let aabbs = blocks
+                .iter()
+                .map(|pos| Aabb::new(*pos, Vector3f::repeat(0.5)))
+                .collect::<Vec<_>>();
+            ray.intersect_aabbs(&aabbs)
+                .map(|(i, (t, _))| (t, blocks[i]))
+        })
+        .min_by(|(t1, _), (t2, _)| t1.partial_cmp(t2).unwrap())
+}
+
+fn bench_raycast(c: &mut Criterion) {
+    let ray = Ray::new(Point3f::new(0.3, -6.9, 0.2), Vector3f::new(1.0, -0.3, 0.7));
+    let reach = 8.0;
+    let floors = vec![new_floor(0), new_floor(1), new_floor(2)];
+    for Floor { chunk_map } in &floors {
+        let (t, pos) = intersect_aabbs(chunk_map, &ray).unwrap();
+        let hit = chunk_map.raycast(&ray, reach).unwrap();
+        assert_eq!(hit.pos, pos);
+        assert!(f32::abs(hit.t - t) < 1e-4);
+    }
+
+    c.bench(
+        "Raycast blocks",
+        ParameterizedBenchmark::new(
+            "AABBs",
+            move |b, Floor { chunk_map }| b.iter(|| intersect_aabbs(chunk_map, &ray)),
+            floors,
+        )
+        .with_function("DDA", move |b, Floor { chunk_map }| {
+            b.iter(|| chunk_map.raycast(&ray, reach))
+        }),
+    );
+}
+
+criterion_group!(benches, bench_raycast);
+criterion_main!(benches);
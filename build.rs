@@ -1,115 +1,123 @@
-use std::process::Command;
+use std::{
+    env,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
-fn main() {
-    // graphics
-    if !Command::new("D:/VulkanSDK/1.1.85.0/Bin/glslangValidator.exe")
-        .args(&[
-            "-V",
-            "src/shaders/graphics.vert",
-            "-o",
-            "src/shaders/graphics-vert.spv",
-        ])
-        .status()
-        .unwrap()
-        .success()
-    {
-        panic!("failed to compile graphics.vert");
+const SHADER_DIR: &str = "src/shaders";
+const SHADER_STAGES: [&str; 3] = ["vert", "frag", "comp"];
+/// Directory of prebuilt SPIR-V, named like the compiled shaders, used if no compiler is found
+const PREBUILT_SPIRV_VAR: &str = "MINECRUST_SPIRV_DIR";
+
+#[derive(Debug, Clone, Copy)]
+enum CompilerKind {
+    GlslangValidator,
+    Glslc,
+}
+
+/// Looks for a shader compiler in `$VULKAN_SDK/bin`, then on `PATH`. glslangValidator is preferred
+/// over glslc.
+fn find_compiler() -> Option<(PathBuf, CompilerKind)> {
+    let mut dirs = vec![];
+    if let Some(sdk) = env::var_os("VULKAN_SDK") {
+        let sdk = PathBuf::from(sdk);
+        dirs.push(sdk.join("bin"));
+        dirs.push(sdk.join("Bin"));
     }
-    if !Command::new("D:/VulkanSDK/1.1.85.0/Bin/glslangValidator.exe")
-        .args(&[
-            "-V",
-            "src/shaders/graphics.frag",
-            "-o",
-            "src/shaders/graphics-frag.spv",
-        ])
-        .status()
-        .unwrap()
-        .success()
-    {
-        panic!("failed to compile graphics.frag");
+    if let Some(path) = env::var_os("PATH") {
+        dirs.extend(env::split_paths(&path));
     }
 
-    // text
-    if !Command::new("D:/VulkanSDK/1.1.85.0/Bin/glslangValidator.exe")
-        .args(&[
-            "-V",
-            "src/shaders/text.vert",
-            "-o",
-            "src/shaders/text-vert.spv",
-        ])
-        .status()
-        .unwrap()
-        .success()
-    {
-        panic!("failed to compile text.vert");
-    }
-    if !Command::new("D:/VulkanSDK/1.1.85.0/Bin/glslangValidator.exe")
-        .args(&[
-            "-V",
-            "src/shaders/text.frag",
-            "-o",
-            "src/shaders/text-frag.spv",
-        ])
-        .status()
-        .unwrap()
-        .success()
-    {
-        panic!("failed to compile text.frag");
+    let exe = |name: &str| -> OsString {
+        if cfg!(windows) {
+            format!("{}.exe", name).into()
+        } else {
+            name.into()
+        }
+    };
+    for &(name, kind) in &[
+        ("glslangValidator", CompilerKind::GlslangValidator),
+        ("glslc", CompilerKind::Glslc),
+    ] {
+        for dir in &dirs {
+            let path = dir.join(exe(name));
+            if path.is_file() {
+                return Some((path, kind));
+            }
+        }
     }
+    None
+}
 
-    // selection
-    if !Command::new("D:/VulkanSDK/1.1.85.0/Bin/glslangValidator.exe")
-        .args(&[
-            "-V",
-            "src/shaders/selection.vert",
-            "-o",
-            "src/shaders/selection-vert.spv",
-        ])
-        .status()
-        .unwrap()
-        .success()
-    {
-        panic!("failed to compile selection.vert");
+/// Returns the shader sources in `SHADER_DIR` and the SPIR-V file in `out_dir` each one compiles
+/// to, e.g. `graphics.vert` compiles to `graphics-vert.spv`.
+fn shaders(out_dir: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let mut shaders = vec![];
+    for entry in fs::read_dir(SHADER_DIR).expect("failed to read shader directory") {
+        let path = entry.unwrap().path();
+        let (stem, stage) = match (path.file_stem(), path.extension()) {
+            (Some(stem), Some(stage)) => (stem.to_string_lossy(), stage.to_string_lossy()),
+            _ => continue,
+        };
+        if SHADER_STAGES.contains(&stage.as_ref()) {
+            let spv = out_dir.join(format!("{}-{}.spv", stem, stage));
+            shaders.push((path.clone(), spv));
+        }
     }
-    if !Command::new("D:/VulkanSDK/1.1.85.0/Bin/glslangValidator.exe")
-        .args(&[
-            "-V",
-            "src/shaders/selection.frag",
-            "-o",
-            "src/shaders/selection-frag.spv",
-        ])
+    shaders.sort();
+    shaders
+}
+
+fn compile(compiler: &Path, kind: CompilerKind, src: &Path, spv: &Path) {
+    let mut command = Command::new(compiler);
+    match kind {
+        CompilerKind::GlslangValidator => command.arg("-V"),
+        CompilerKind::Glslc => command.arg("-c"),
+    };
+    let status = command
+        .arg(src)
+        .arg("-o")
+        .arg(spv)
         .status()
-        .unwrap()
-        .success()
-    {
-        panic!("failed to compile selection.frag");
+        .unwrap_or_else(|err| panic!("failed to run {}: {}", compiler.display(), err));
+    if !status.success() {
+        panic!("failed to compile {}", src.display());
     }
+}
 
-    // crosshair
-    if !Command::new("D:/VulkanSDK/1.1.85.0/Bin/glslangValidator.exe")
-        .args(&[
-            "-V",
-            "src/shaders/crosshair.vert",
-            "-o",
-            "src/shaders/crosshair-vert.spv",
-        ])
-        .status()
-        .unwrap()
-        .success()
-    {
-        panic!("failed to compile crosshair.vert");
+/// Copies the prebuilt SPIR-V for `spv` from `prebuilt_dir`.
+fn copy_prebuilt(prebuilt_dir: &Path, spv: &Path) {
+    let prebuilt = prebuilt_dir.join(spv.file_name().unwrap());
+    println!("cargo:rerun-if-changed={}", prebuilt.display());
+    fs::copy(&prebuilt, spv)
+        .unwrap_or_else(|err| panic!("failed to copy {}: {}", prebuilt.display(), err));
+}
+
+fn main() {
+    // Only the sources are watched; the SPIR-V goes to `OUT_DIR`, where it doesn't dirty the build
+    println!("cargo:rerun-if-changed={}", SHADER_DIR);
+    println!("cargo:rerun-if-env-changed=VULKAN_SDK");
+    println!("cargo:rerun-if-env-changed=PATH");
+    println!("cargo:rerun-if-env-changed={}", PREBUILT_SPIRV_VAR);
+
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR is not set"));
+    let shaders = shaders(&out_dir);
+    let compiler = find_compiler();
+    let prebuilt_dir = env::var_os(PREBUILT_SPIRV_VAR).map(PathBuf::from);
+    if compiler.is_none() && prebuilt_dir.is_none() {
+        panic!(
+            "no glslangValidator or glslc found in $VULKAN_SDK/bin or on PATH, and ${} isn't set",
+            PREBUILT_SPIRV_VAR
+        );
     }
-    if !Command::new("D:/VulkanSDK/1.1.85.0/Bin/glslangValidator.exe")
-        .args(&[
-            "-V",
-            "src/shaders/crosshair.frag",
-            "-o",
-            "src/shaders/crosshair-frag.spv",
-        ])
-        .status()
-        .unwrap()
-        .success()
-    {
-        panic!("failed to compile crosshair.frag");
+    for (src, spv) in &shaders {
+        println!("cargo:rerun-if-changed={}", src.display());
+        match (&compiler, &prebuilt_dir) {
+            (Some((compiler, kind)), _) => compile(compiler, *kind, src, spv),
+            (None, Some(prebuilt_dir)) => copy_prebuilt(prebuilt_dir, spv),
+            (None, None) => unreachable!(),
+        }
     }
 }
@@ -0,0 +1,153 @@
+use failure_derive::Fail;
+use image::{imageops, FilterType, RgbaImage};
+use std::path::Path;
+
+#[derive(Fail, Debug)]
+pub enum AtlasError {
+    #[fail(display = "{}", _0)]
+    Image(#[cause] image::ImageError),
+    #[fail(display = "texture atlas has no textures")]
+    Empty,
+}
+
+impl From<image::ImageError> for AtlasError {
+    fn from(err: image::ImageError) -> AtlasError {
+        AtlasError::Image(err)
+    }
+}
+
+/// Textures packed into the layers of an array texture, so that they can all be sampled with one
+/// descriptor set. Layer `i` holds the `i`th texture, so a `TextureId` is also its layer index.
+///
+/// Each texture gets a whole layer, so UVs don't need to be remapped and can still tile.
+#[derive(Debug, Clone)]
+pub struct TextureAtlas {
+    width: u32,
+    height: u32,
+    layers: Vec<RgbaImage>,
+}
+
+impl TextureAtlas {
+    /// Packs `images` into layers the size of the largest one. Smaller images are scaled up with
+    /// nearest-neighbor filtering to keep their pixels sharp.
+    pub fn new(images: Vec<RgbaImage>) -> Result<TextureAtlas, AtlasError> {
+        let width = images
+            .iter()
+            .map(|img| img.width())
+            .max()
+            .ok_or(AtlasError::Empty)?;
+        let height = images.iter().map(|img| img.height()).max().unwrap();
+        let layers = images
+            .into_iter()
+            .map(|img| {
+                if img.dimensions() == (width, height) {
+                    img
+                } else {
+                    imageops::resize(&img, width, height, FilterType::Nearest)
+                }
+            })
+            .collect();
+        Ok(TextureAtlas {
+            width,
+            height,
+            layers,
+        })
+    }
+
+    /// Loads the images at `paths`, in order.
+    pub fn load<P: AsRef<Path>>(paths: &[P]) -> Result<TextureAtlas, AtlasError> {
+        let mut images = vec![];
+        for path in paths {
+            images.push(image::open(path)?.to_rgba());
+        }
+        TextureAtlas::new(images)
+    }
+
+    /// Returns the (width, height) of each layer.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn layer(&self, i: usize) -> &RgbaImage {
+        &self.layers[i]
+    }
+
+    /// Number of mip levels needed to shrink a layer down to 1x1.
+    pub fn mip_levels(&self) -> u32 {
+        f32::floor(f32::log2(u32::max(self.width, self.height) as f32)) as u32 + 1
+    }
+
+    /// Returns the RGBA pixels of all layers, one layer after another.
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity((self.width * self.height * 4) as usize * self.len());
+        for layer in &self.layers {
+            bytes.extend_from_slice(layer);
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn solid(width: u32, height: u32, value: u8) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba([value, value, value, 255]))
+    }
+
+    #[test]
+    fn test_new() {
+        let atlas = TextureAtlas::new(vec![solid(16, 16, 1), solid(16, 16, 2)]).unwrap();
+        assert_eq!(atlas.len(), 2);
+        assert_eq!(atlas.dimensions(), (16, 16));
+        assert_eq!(atlas.mip_levels(), 5);
+        // Layers keep the order of the images
+        assert_eq!(atlas.layer(0).get_pixel(3, 4), &Rgba([1, 1, 1, 255]));
+        assert_eq!(atlas.layer(1).get_pixel(3, 4), &Rgba([2, 2, 2, 255]));
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut small = solid(2, 2, 0);
+        small.put_pixel(1, 0, Rgba([9, 9, 9, 255]));
+        let atlas = TextureAtlas::new(vec![small, solid(8, 4, 1)]).unwrap();
+        assert_eq!(atlas.dimensions(), (8, 4));
+        let layer = atlas.layer(0);
+        assert_eq!(layer.dimensions(), (8, 4));
+        // Nearest filtering doesn't blend pixels
+        assert_eq!(layer.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+        assert_eq!(layer.get_pixel(7, 0), &Rgba([9, 9, 9, 255]));
+        assert_eq!(layer.get_pixel(7, 3), &Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_bytes() {
+        let atlas =
+            TextureAtlas::new(vec![solid(2, 2, 1), solid(2, 2, 2), solid(2, 2, 3)]).unwrap();
+        let bytes = atlas.bytes();
+        assert_eq!(bytes.len(), 2 * 2 * 4 * 3);
+        for (i, layer) in bytes.chunks(2 * 2 * 4).enumerate() {
+            for pixel in layer.chunks(4) {
+                let value = i as u8 + 1;
+                assert_eq!(pixel, &[value, value, value, 255]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        match TextureAtlas::new(vec![]) {
+            Err(AtlasError::Empty) => (),
+            result => panic!("{:?}", result),
+        }
+    }
+}
@@ -1,20 +1,46 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum BlockType {
-    Cobblestone,
-    Sandstone,
-}
+mod registry;
+
+pub use self::registry::{BlockDef, BlockRegistry, BlockRegistryError, TextureId, BLOCKS_PATH};
+
+/// Identifies a kind of block. The properties of each block type are defined in the block
+/// registry, which is loaded from `BLOCKS_PATH`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockType(u16);
+
+impl BlockType {
+    pub fn new(id: u16) -> BlockType {
+        BlockType(id)
+    }
+
+    pub fn id(self) -> u16 {
+        self.0
+    }
 
-pub const NUM_BLOCK_TYPES: usize = 2;
+    /// Looks up a block type by name in the global registry. Panics if there is no such block.
+    pub fn named(name: &str) -> BlockType {
+        BlockRegistry::global()
+            .by_name(name)
+            .unwrap_or_else(|| panic!("unknown block type {}", name))
+    }
+
+    /// Returns this block type's definition in the global registry.
+    pub fn def(self) -> &'static BlockDef {
+        BlockRegistry::global().get(self)
+    }
+
+    /// Whether the block hides the faces of blocks next to it.
+    pub fn is_opaque(self) -> bool {
+        self.def().opaque
+    }
+
+    /// Whether the block blocks movement.
+    pub fn is_solid(self) -> bool {
+        self.def().solid
+    }
+}
 
 impl std::fmt::Display for BlockType {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                BlockType::Cobblestone => "cobblestone",
-                BlockType::Sandstone => "sandstone",
-            }
-        )
+        write!(f, "{}", self.def().name)
     }
 }
@@ -0,0 +1,375 @@
+use super::BlockType;
+use crate::types::prelude::*;
+use failure_derive::Fail;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::{collections::HashMap, fs, path::Path};
+
+/// Path of the block definitions loaded by `BlockRegistry::global()`
+pub const BLOCKS_PATH: &str = "assets/blocks.ron";
+
+/// Index of a texture in `BlockRegistry::textures()`
+pub type TextureId = usize;
+
+lazy_static! {
+    static ref REGISTRY: BlockRegistry = BlockRegistry::load(BLOCKS_PATH)
+        .unwrap_or_else(|err| panic!("failed to load {}: {}", BLOCKS_PATH, err));
+}
+
+#[derive(Fail, Debug)]
+pub enum BlockRegistryError {
+    #[fail(display = "{}", _0)]
+    Io(#[cause] std::io::Error),
+    #[fail(display = "{}", _0)]
+    Parse(#[cause] ron::de::Error),
+    #[fail(display = "duplicate block id {}", _0)]
+    DuplicateId(u16),
+    /// Saved chunks use block id 0 for empty blocks
+    #[fail(display = "block {} has the reserved id 0", _0)]
+    ReservedId(String),
+    #[fail(display = "duplicate block name {}", _0)]
+    DuplicateName(String),
+    #[fail(display = "block {} has textures for unknown face {}", block, face)]
+    UnknownFace { block: String, face: String },
+    #[fail(display = "block {} has no texture for face {:?}", block, face)]
+    MissingTexture { block: String, face: Face },
+}
+
+impl From<std::io::Error> for BlockRegistryError {
+    fn from(err: std::io::Error) -> BlockRegistryError {
+        BlockRegistryError::Io(err)
+    }
+}
+
+impl From<ron::de::Error> for BlockRegistryError {
+    fn from(err: ron::de::Error) -> BlockRegistryError {
+        BlockRegistryError::Parse(err)
+    }
+}
+
+/// A block definition as written in the data file.
+#[derive(Deserialize)]
+struct RawBlockDef {
+    id: u16,
+    name: String,
+    /// Maps "all", "side" or a face name to a texture path. More specific keys take precedence.
+    textures: HashMap<String, String>,
+    #[serde(default = "default_true")]
+    opaque: bool,
+    #[serde(default = "default_true")]
+    solid: bool,
+    #[serde(default = "default_hardness")]
+    hardness: f32,
+    #[serde(default)]
+    light: u8,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+fn face_key(face: Face) -> &'static str {
+    match face {
+        Face::Top => "top",
+        Face::Front => "front",
+        Face::Left => "left",
+        Face::Right => "right",
+        Face::Back => "back",
+        Face::Bottom => "bottom",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockDef {
+    pub block_type: BlockType,
+    pub name: String,
+    /// Texture path for each face, indexed by `Face as usize`
+    pub textures: [String; 6],
+    /// `TextureId` of each face's texture, indexed by `Face as usize`
+    pub texture_ids: [TextureId; 6],
+    /// Whether the block hides the faces of blocks next to it
+    pub opaque: bool,
+    /// Whether the block blocks movement
+    pub solid: bool,
+    pub hardness: f32,
+    /// Light level emitted by the block
+    pub light: u8,
+}
+
+impl BlockDef {
+    pub fn texture(&self, face: Face) -> &str {
+        &self.textures[face as usize]
+    }
+
+    pub fn texture_id(&self, face: Face) -> TextureId {
+        self.texture_ids[face as usize]
+    }
+
+    fn from_raw(raw: RawBlockDef) -> Result<BlockDef, BlockRegistryError> {
+        let keys = ["all", "side"];
+        for key in raw.textures.keys() {
+            if !keys.contains(&key.as_str()) && !Face::ALL.iter().any(|&f| face_key(f) == key) {
+                return Err(BlockRegistryError::UnknownFace {
+                    block: raw.name,
+                    face: key.clone(),
+                });
+            }
+        }
+
+        let mut textures: [String; 6] = Default::default();
+        for &face in &Face::ALL {
+            let side = match face {
+                Face::Top | Face::Bottom => None,
+                _ => raw.textures.get("side"),
+            };
+            textures[face as usize] = raw
+                .textures
+                .get(face_key(face))
+                .or(side)
+                .or_else(|| raw.textures.get("all"))
+                .ok_or_else(|| BlockRegistryError::MissingTexture {
+                    block: raw.name.clone(),
+                    face,
+                })?
+                .clone();
+        }
+
+        Ok(BlockDef {
+            block_type: BlockType::new(raw.id),
+            name: raw.name,
+            textures,
+            texture_ids: [0; 6],
+            opaque: raw.opaque,
+            solid: raw.solid,
+            hardness: raw.hardness,
+            light: raw.light,
+        })
+    }
+}
+
+/// The set of all block types, loaded from a RON file holding a list of block definitions.
+#[derive(Debug, Clone)]
+pub struct BlockRegistry {
+    /// Sorted by block type
+    blocks: Vec<BlockDef>,
+    by_name: HashMap<String, BlockType>,
+    /// Distinct texture paths, indexed by `TextureId`
+    textures: Vec<String>,
+}
+
+impl BlockRegistry {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<BlockRegistry, BlockRegistryError> {
+        BlockRegistry::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(s: &str) -> Result<BlockRegistry, BlockRegistryError> {
+        let raw_defs: Vec<RawBlockDef> = ron::de::from_str(s)?;
+        let mut blocks = vec![];
+        let mut by_name = HashMap::new();
+        for raw in raw_defs {
+            if raw.id == 0 {
+                return Err(BlockRegistryError::ReservedId(raw.name));
+            }
+            let def = BlockDef::from_raw(raw)?;
+            if by_name.insert(def.name.clone(), def.block_type).is_some() {
+                return Err(BlockRegistryError::DuplicateName(def.name));
+            }
+            blocks.push(def);
+        }
+        blocks.sort_by_key(|def| def.block_type);
+        for pair in blocks.windows(2) {
+            if pair[0].block_type == pair[1].block_type {
+                return Err(BlockRegistryError::DuplicateId(pair[0].block_type.id()));
+            }
+        }
+
+        let mut textures: Vec<String> = vec![];
+        for def in &mut blocks {
+            for &face in &Face::ALL {
+                let path = &def.textures[face as usize];
+                def.texture_ids[face as usize] = match textures.iter().position(|t| t == path) {
+                    Some(id) => id,
+                    None => {
+                        textures.push(path.clone());
+                        textures.len() - 1
+                    }
+                };
+            }
+        }
+        Ok(BlockRegistry {
+            blocks,
+            by_name,
+            textures,
+        })
+    }
+
+    /// The registry loaded from `BLOCKS_PATH`. Panics if it can't be loaded.
+    pub fn global() -> &'static BlockRegistry {
+        &REGISTRY
+    }
+
+    /// Panics if `block_type` isn't in the registry.
+    pub fn get(&self, block_type: BlockType) -> &BlockDef {
+        match self
+            .blocks
+            .binary_search_by_key(&block_type, |def| def.block_type)
+        {
+            Ok(i) => &self.blocks[i],
+            Err(_) => panic!("unknown block id {}", block_type.id()),
+        }
+    }
+
+    pub fn contains(&self, block_type: BlockType) -> bool {
+        self.blocks
+            .binary_search_by_key(&block_type, |def| def.block_type)
+            .is_ok()
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<BlockType> {
+        self.by_name.get(name).cloned()
+    }
+
+    pub fn blocks(&self) -> std::slice::Iter<'_, BlockDef> {
+        self.blocks.iter()
+    }
+
+    /// Paths of all textures used by blocks, indexed by `TextureId`.
+    pub fn textures(&self) -> &[String] {
+        &self.textures
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let registry = BlockRegistry::parse(
+            r#"[
+                (
+                    id: 3,
+                    name: "log",
+                    textures: {"all": "log_end.png", "side": "log.png"},
+                    hardness: 2.0,
+                ),
+                (
+                    id: 1,
+                    name: "glowstone",
+                    textures: {"all": "glowstone.png", "top": "glowstone_top.png"},
+                    opaque: false,
+                    light: 15,
+                ),
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(registry.len(), 2);
+
+        let log = registry.get(registry.by_name("log").unwrap());
+        assert_eq!(log.block_type, BlockType::new(3));
+        assert_eq!(log.texture(Face::Top), "log_end.png");
+        assert_eq!(log.texture(Face::Bottom), "log_end.png");
+        assert_eq!(log.texture(Face::Left), "log.png");
+        assert_eq!(log.texture(Face::Front), "log.png");
+        assert!(log.opaque);
+        assert!(log.solid);
+        assert_eq!(log.hardness, 2.0);
+        assert_eq!(log.light, 0);
+
+        let glowstone = registry.get(BlockType::new(1));
+        assert_eq!(glowstone.name, "glowstone");
+        assert_eq!(glowstone.texture(Face::Top), "glowstone_top.png");
+        assert_eq!(glowstone.texture(Face::Back), "glowstone.png");
+        assert!(!glowstone.opaque);
+        assert_eq!(glowstone.hardness, 1.0);
+        assert_eq!(glowstone.light, 15);
+
+        // Textures are shared between faces and numbered in block order
+        assert_eq!(
+            registry.textures(),
+            &[
+                "glowstone_top.png",
+                "glowstone.png",
+                "log_end.png",
+                "log.png"
+            ]
+        );
+        assert_eq!(glowstone.texture_id(Face::Top), 0);
+        assert_eq!(glowstone.texture_id(Face::Bottom), 1);
+        assert_eq!(log.texture_id(Face::Top), 2);
+        assert_eq!(log.texture_id(Face::Bottom), 2);
+        assert_eq!(log.texture_id(Face::Right), 3);
+
+        // Sorted by id
+        assert_eq!(
+            registry
+                .blocks()
+                .map(|def| def.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["glowstone", "log"]
+        );
+        assert_eq!(registry.by_name("dirt"), None);
+        assert!(registry.contains(BlockType::new(3)));
+        assert!(!registry.contains(BlockType::new(2)));
+    }
+
+    #[test]
+    fn test_errors() {
+        match BlockRegistry::parse(
+            r#"[(id: 1, name: "a", textures: {"all": "a.png"}),
+                (id: 1, name: "b", textures: {"all": "b.png"})]"#,
+        ) {
+            Err(BlockRegistryError::DuplicateId(1)) => (),
+            result => panic!("{:?}", result),
+        }
+        match BlockRegistry::parse(
+            r#"[(id: 1, name: "a", textures: {"all": "a.png"}),
+                (id: 2, name: "a", textures: {"all": "b.png"})]"#,
+        ) {
+            Err(BlockRegistryError::DuplicateName(ref name)) if name == "a" => (),
+            result => panic!("{:?}", result),
+        }
+        match BlockRegistry::parse(r#"[(id: 0, name: "a", textures: {"all": "a.png"})]"#) {
+            Err(BlockRegistryError::ReservedId(ref name)) if name == "a" => (),
+            result => panic!("{:?}", result),
+        }
+        match BlockRegistry::parse(r#"[(id: 1, name: "a", textures: {"up": "a.png"})]"#) {
+            Err(BlockRegistryError::UnknownFace { ref face, .. }) if face == "up" => (),
+            result => panic!("{:?}", result),
+        }
+        match BlockRegistry::parse(r#"[(id: 1, name: "a", textures: {"side": "a.png"})]"#) {
+            Err(BlockRegistryError::MissingTexture {
+                face: Face::Top, ..
+            }) => (),
+            result => panic!("{:?}", result),
+        }
+        match BlockRegistry::parse(r#"[(id: 1, name: "a")]"#) {
+            Err(BlockRegistryError::Parse(_)) => (),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_global() {
+        let registry = BlockRegistry::global();
+        assert!(!registry.is_empty());
+        for def in registry.blocks() {
+            assert_eq!(registry.by_name(&def.name), Some(def.block_type));
+            for &face in &Face::ALL {
+                assert!(Path::new(def.texture(face)).is_file());
+            }
+        }
+    }
+}
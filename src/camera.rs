@@ -1,5 +1,6 @@
 use crate::{
-    na::Unit,
+    geometry::Frustum,
+    na::{geometry::Perspective3, Unit},
     types::prelude::*,
     utils::{point3f, quaternion4f},
 };
@@ -8,6 +9,13 @@ use std::{
     f32::consts::{FRAC_PI_2, PI},
 };
 
+/// Vertical field of view, in degrees
+pub const FOV_Y: f32 = 45.0;
+/// Distance to the near clipping plane
+pub const Z_NEAR: f32 = 0.1;
+/// Distance to the far clipping plane
+pub const Z_FAR: f32 = 100.0;
+
 #[derive(Debug, Clone)]
 pub struct Camera {
     pub pos: Point3f,
@@ -51,6 +59,23 @@ impl Camera {
         )
     }
 
+    /// Perspective projection onto Vulkan's clip volume, where y points down and z goes from 0 to
+    /// w, for a screen with `aspect` = width / height.
+    pub fn projection(aspect: f32) -> Matrix4f {
+        let mut flip_mat = Matrix4f::from_diagonal(&Vector4f::new(1.0, -1.0, 0.5, 1.0));
+        flip_mat[(2, 3)] = 0.5;
+        flip_mat * Perspective3::new(aspect, FOV_Y.to_radians(), Z_NEAR, Z_FAR).to_homogeneous()
+    }
+
+    pub fn proj_view(&self, aspect: f32) -> Matrix4f {
+        Camera::projection(aspect) * self.to_matrix()
+    }
+
+    /// The part of the world visible on a screen with `aspect` = width / height.
+    pub fn frustum(&self, aspect: f32) -> Frustum {
+        Frustum::from_matrix(&self.proj_view(aspect))
+    }
+
     pub fn direction(&self) -> Unit<Vector3f> {
         self.yaw_q * (self.pitch_q * Vector3f::z_axis())
     }
@@ -0,0 +1,124 @@
+use crate::vector::Vector2D;
+
+/// A rectangle of identical cells in a slice, as produced by `merge`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Quad<T> {
+    pub value: T,
+    /// Lowest `(u, v)` cell covered by the quad
+    pub min: (i32, i32),
+    /// Number of cells along u
+    pub width: usize,
+    /// Number of cells along v
+    pub height: usize,
+}
+
+/// Greedily merges the filled cells of `slice` into as few rectangles as it can. Adjacent cells are
+/// only merged if they hold equal values.
+pub fn merge<T: Copy + PartialEq>(slice: &Vector2D<Option<T>>) -> Vec<Quad<T>> {
+    let side_len = slice.side_len;
+    let half = (side_len / 2) as i32;
+    let mut merged = vec![false; side_len * side_len];
+    let cell = |u: usize, v: usize| u + v * side_len;
+    let mut quads = vec![];
+
+    for v in 0..side_len {
+        let mut u = 0;
+        while u < side_len {
+            let value = match slice[cell(u, v)] {
+                Some(value) if !merged[cell(u, v)] => value,
+                _ => {
+                    u += 1;
+                    continue;
+                }
+            };
+            let mergeable =
+                |u: usize, v: usize| !merged[cell(u, v)] && slice[cell(u, v)] == Some(value);
+
+            let mut width = 1;
+            while u + width < side_len && mergeable(u + width, v) {
+                width += 1;
+            }
+            let mut height = 1;
+            while v + height < side_len && (u..u + width).all(|u| mergeable(u, v + height)) {
+                height += 1;
+            }
+
+            for dv in 0..height {
+                for du in 0..width {
+                    merged[cell(u + du, v + dv)] = true;
+                }
+            }
+            quads.push(Quad {
+                value,
+                min: (u as i32 - half, v as i32 - half),
+                width,
+                height,
+            });
+            u += width;
+        }
+    }
+    quads
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_full() {
+        let slice = Vector2D::new(5, Some(1));
+        assert_eq!(
+            merge(&slice),
+            vec![Quad {
+                value: 1,
+                min: (-2, -2),
+                width: 5,
+                height: 5,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_merge_empty() {
+        let slice: Vector2D<Option<u8>> = Vector2D::new_default(5);
+        assert!(merge(&slice).is_empty());
+    }
+
+    #[test]
+    fn test_merge_values() {
+        // 1 1 2
+        // 1 1 2
+        // . 1 .
+        let mut slice = Vector2D::new_default(3);
+        slice[(-1, -1)] = Some(1);
+        slice[(0, -1)] = Some(1);
+        slice[(1, -1)] = Some(2);
+        slice[(-1, 0)] = Some(1);
+        slice[(0, 0)] = Some(1);
+        slice[(1, 0)] = Some(2);
+        slice[(0, 1)] = Some(1);
+        assert_eq!(
+            merge(&slice),
+            vec![
+                Quad {
+                    value: 1,
+                    min: (-1, -1),
+                    width: 2,
+                    height: 2,
+                },
+                Quad {
+                    value: 2,
+                    min: (1, -1),
+                    width: 1,
+                    height: 2,
+                },
+                Quad {
+                    value: 1,
+                    min: (0, 1),
+                    width: 1,
+                    height: 1,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,233 @@
+use crate::types::prelude::*;
+use std::ops::Index;
+
+/// A cube of `T`s that stores each cell as a bit-packed index into a palette of distinct values.
+/// Uses the same centered indexing as `Vector3D`. A cube holding a single value uses no index
+/// storage at all.
+#[derive(Debug, Clone)]
+pub struct PalettedVector3D<T> {
+    pub side_len: usize,
+    palette: Vec<T>,
+    /// Number of cells referencing each palette entry. Entries with a count of 0 are reused.
+    ref_counts: Vec<usize>,
+    bits_per_index: usize,
+    indices: Vec<u64>,
+}
+
+impl<T: Clone + PartialEq> PalettedVector3D<T> {
+    pub fn new(side_len: usize, t: T) -> PalettedVector3D<T> {
+        assert_eq!(side_len % 2, 1, "side_len must be odd");
+        PalettedVector3D {
+            side_len,
+            palette: vec![t],
+            ref_counts: vec![side_len * side_len * side_len],
+            bits_per_index: 0,
+            indices: vec![],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.side_len * self.side_len * self.side_len
+    }
+
+    /// Number of distinct values currently in use.
+    pub fn palette_len(&self) -> usize {
+        self.ref_counts.iter().filter(|&&count| count > 0).count()
+    }
+
+    pub fn set(&mut self, i: usize, t: T) {
+        let old = self.palette_index(i);
+        if self.palette[old] == t {
+            return;
+        }
+        let new = match self.palette.iter().position(|p| *p == t) {
+            Some(new) => new,
+            None => match self.ref_counts.iter().position(|&count| count == 0) {
+                Some(new) => {
+                    self.palette[new] = t;
+                    new
+                }
+                None => {
+                    self.palette.push(t);
+                    self.ref_counts.push(0);
+                    if self.palette.len() > 1 << self.bits_per_index {
+                        self.grow();
+                    }
+                    self.palette.len() - 1
+                }
+            },
+        };
+        self.ref_counts[old] -= 1;
+        self.ref_counts[new] += 1;
+        self.set_palette_index(i, new);
+    }
+
+    pub fn set_at(&mut self, i: (i32, i32, i32), t: T) {
+        let i = self.linear_index(i);
+        self.set(i, t);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        (0..self.len()).map(move |i| &self[i])
+    }
+}
+
+impl<T> PalettedVector3D<T> {
+    /// Converts centered `(x, y, z)` coordinates into an index into the underlying storage.
+    pub fn linear_index(&self, (x, y, z): (i32, i32, i32)) -> usize {
+        let offset = (self.side_len / 2) as i32;
+        let x = (x + offset) as usize;
+        let y = (y + offset) as usize;
+        let z = (z + offset) as usize;
+        assert!(x < self.side_len);
+        assert!(y < self.side_len);
+        assert!(z < self.side_len);
+        x + y * self.side_len + z * self.side_len * self.side_len
+    }
+
+    /// Inverse of `linear_index`.
+    pub fn coords(&self, i: usize) -> (i32, i32, i32) {
+        let offset = (self.side_len / 2) as i32;
+        let x = i % self.side_len;
+        let y = (i / self.side_len) % self.side_len;
+        let z = i / (self.side_len * self.side_len);
+        (x as i32 - offset, y as i32 - offset, z as i32 - offset)
+    }
+
+    fn indices_per_word(&self) -> usize {
+        64 / self.bits_per_index
+    }
+
+    fn palette_index(&self, i: usize) -> usize {
+        if self.bits_per_index == 0 {
+            return 0;
+        }
+        let per_word = self.indices_per_word();
+        let shift = (i % per_word) * self.bits_per_index;
+        let mask = (1 << self.bits_per_index) - 1;
+        ((self.indices[i / per_word] >> shift) & mask) as usize
+    }
+
+    fn set_palette_index(&mut self, i: usize, palette_index: usize) {
+        let per_word = self.indices_per_word();
+        let shift = (i % per_word) * self.bits_per_index;
+        let mask: u64 = (1 << self.bits_per_index) - 1;
+        let word = &mut self.indices[i / per_word];
+        *word = (*word & !(mask << shift)) | ((palette_index as u64) << shift);
+    }
+
+    /// Doubles the number of bits used per index and repacks every cell.
+    fn grow(&mut self) {
+        let len = self.side_len * self.side_len * self.side_len;
+        let old_indices: Vec<usize> = (0..len).map(|i| self.palette_index(i)).collect();
+        self.bits_per_index = match self.bits_per_index {
+            0 => 1,
+            bits => bits * 2,
+        };
+        let per_word = self.indices_per_word();
+        // Growing means a value was set, so `len` isn't 0
+        self.indices = vec![0; (len - 1) / per_word + 1];
+        for (i, palette_index) in old_indices.into_iter().enumerate() {
+            self.set_palette_index(i, palette_index);
+        }
+    }
+}
+
+impl<T> Index<usize> for PalettedVector3D<T> {
+    type Output = T;
+
+    fn index(&self, i: usize) -> &Self::Output {
+        assert!(i < self.side_len * self.side_len * self.side_len);
+        &self.palette[self.palette_index(i)]
+    }
+}
+
+impl<T> Index<(i32, i32, i32)> for PalettedVector3D<T> {
+    type Output = T;
+
+    fn index(&self, i: (i32, i32, i32)) -> &Self::Output {
+        &self[self.linear_index(i)]
+    }
+}
+
+impl<T> Index<Vector3f> for PalettedVector3D<T> {
+    type Output = T;
+
+    fn index(&self, i: Vector3f) -> &Self::Output {
+        assert!(i.almost_is_int());
+        &self[(i.x as i32, i.y as i32, i.z as i32)]
+    }
+}
+
+impl<T: Clone + PartialEq> PartialEq for PalettedVector3D<T> {
+    fn eq(&self, other: &PalettedVector3D<T>) -> bool {
+        self.side_len == other.side_len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Clone + Eq> Eq for PalettedVector3D<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{distributions::Uniform, rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_uniform() {
+        let vec = PalettedVector3D::new(5, 7u8);
+        assert_eq!(vec.bits_per_index, 0);
+        assert_eq!(vec.palette_len(), 1);
+        assert!(vec.iter().all(|&t| t == 7));
+        assert_eq!(vec[(2, -2, 0)], 7);
+    }
+
+    #[test]
+    fn test_set() {
+        let mut vec = PalettedVector3D::new(3, None);
+        vec.set_at((1, 0, -1), Some(4u8));
+        assert_eq!(vec.bits_per_index, 1);
+        assert_eq!(vec[(1, 0, -1)], Some(4));
+        assert_eq!(vec[(0, 0, -1)], None);
+
+        vec.set_at((-1, -1, -1), Some(5));
+        vec.set_at((1, 1, 1), Some(6));
+        assert_eq!(vec.bits_per_index, 2);
+        assert_eq!(vec.palette_len(), 4);
+        assert_eq!(vec[(1, 0, -1)], Some(4));
+        assert_eq!(vec[(-1, -1, -1)], Some(5));
+        assert_eq!(vec[(1, 1, 1)], Some(6));
+
+        // Replacing the only 5 frees its palette entry for reuse.
+        vec.set_at((-1, -1, -1), None);
+        assert_eq!(vec.palette_len(), 3);
+        vec.set_at((0, 0, 0), Some(7));
+        assert_eq!(vec.bits_per_index, 2);
+        assert_eq!(vec[(0, 0, 0)], Some(7));
+        assert_eq!(vec[(-1, -1, -1)], None);
+    }
+
+    #[test]
+    fn test_coords() {
+        let vec = PalettedVector3D::new(5, 0u8);
+        for i in 0..vec.len() {
+            assert_eq!(vec.linear_index(vec.coords(i)), i);
+        }
+        assert_eq!(vec.coords(0), (-2, -2, -2));
+    }
+
+    #[test]
+    fn test_random() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let dist = Uniform::new(0u16, 300);
+        let mut vec = PalettedVector3D::new(9, 0u16);
+        let mut expected = vec![0u16; vec.len()];
+        for _ in 0..10000 {
+            let i = rng.gen_range(0, expected.len());
+            let t = rng.sample(dist);
+            vec.set(i, t);
+            expected[i] = t;
+        }
+        assert!(vec.iter().eq(expected.iter()));
+        assert_eq!(vec.bits_per_index, 16);
+    }
+}
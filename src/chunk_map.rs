@@ -0,0 +1,444 @@
+use crate::{
+    block::BlockType,
+    chunk::{Chunk, ChunkNeighbors},
+    geometry::{Aabb, Frustum, Ray, VoxelHit},
+    types::prelude::*,
+};
+use std::collections::{hash_map, HashMap};
+
+/// Integer coordinates of a chunk. Chunk `(0, 0, 0)` is centered at the origin, chunk `(1, 0, 0)`
+/// is centered at `(side_len, 0, 0)`, and so on.
+pub type ChunkCoords = (i32, i32, i32);
+
+/// A collection of loaded chunks, keyed by chunk coordinates.
+#[derive(Debug, Clone)]
+pub struct ChunkMap {
+    chunk_side_len: usize,
+    chunks: HashMap<ChunkCoords, Chunk>,
+    /// Chunks unloaded since the last call to `take_unloaded()`
+    unloaded: Vec<ChunkCoords>,
+}
+
+impl ChunkMap {
+    pub fn new(chunk_side_len: usize) -> ChunkMap {
+        assert_eq!(chunk_side_len % 2, 1, "chunk_side_len must be odd");
+        ChunkMap {
+            chunk_side_len,
+            chunks: HashMap::new(),
+            unloaded: vec![],
+        }
+    }
+
+    pub fn chunk_side_len(&self) -> usize {
+        self.chunk_side_len
+    }
+
+    /// Returns the coordinates of the chunk that contains the block at `pos`.
+    pub fn chunk_coords(&self, pos: &Point3f) -> ChunkCoords {
+        assert!(pos.almost_is_int());
+        let side_len = self.chunk_side_len as i32;
+        let half = side_len / 2;
+        let f = |x: f32| (x.round() as i32 + half).div_euclid(side_len);
+        (f(pos.x), f(pos.y), f(pos.z))
+    }
+
+    pub fn chunk_center(&self, coords: ChunkCoords) -> Point3f {
+        let side_len = self.chunk_side_len as f32;
+        Point3f::new(
+            coords.0 as f32 * side_len,
+            coords.1 as f32 * side_len,
+            coords.2 as f32 * side_len,
+        )
+    }
+
+    /// Bounding box of the blocks in the chunk at `coords`.
+    pub fn chunk_aabb(&self, coords: ChunkCoords) -> Aabb {
+        Aabb::new(
+            self.chunk_center(coords),
+            Vector3f::repeat(self.chunk_side_len as f32 / 2.0),
+        )
+    }
+
+    pub fn chunk(&self, coords: ChunkCoords) -> Option<&Chunk> {
+        self.chunks.get(&coords)
+    }
+
+    pub fn chunk_mut(&mut self, coords: ChunkCoords) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&coords)
+    }
+
+    /// Returns the chunk at `coords`, creating an empty one if it isn't loaded.
+    pub fn load_chunk(&mut self, coords: ChunkCoords) -> &mut Chunk {
+        if !self.chunks.contains_key(&coords) {
+            let chunk = Chunk::new(self.chunk_center(coords), self.chunk_side_len);
+            self.chunks.insert(coords, chunk);
+            self.mark_neighbors_dirty(coords);
+        }
+        self.chunks.get_mut(&coords).unwrap()
+    }
+
+    /// Loads `chunk` at `coords`, replacing any chunk already loaded there.
+    pub fn insert_chunk(&mut self, coords: ChunkCoords, chunk: Chunk) -> Option<Chunk> {
+        assert_eq!(chunk.center(), self.chunk_center(coords));
+        let old = self.chunks.insert(coords, chunk);
+        self.mark_neighbors_dirty(coords);
+        old
+    }
+
+    pub fn unload_chunk(&mut self, coords: ChunkCoords) -> Option<Chunk> {
+        let chunk = self.chunks.remove(&coords)?;
+        self.mark_neighbors_dirty(coords);
+        self.unloaded.push(coords);
+        Some(chunk)
+    }
+
+    /// Returns the chunks unloaded since the last call, so their meshes can be dropped.
+    pub fn take_unloaded(&mut self) -> Vec<ChunkCoords> {
+        self.unloaded.drain(..).collect()
+    }
+
+    fn mark_neighbors_dirty(&mut self, coords: ChunkCoords) {
+        for face in &Face::ALL {
+            let (dx, dy, dz) = face.offset();
+            if let Some(neighbor) = self.chunk_mut((coords.0 + dx, coords.1 + dy, coords.2 + dz)) {
+                neighbor.mark_dirty();
+            }
+        }
+    }
+
+    /// Rebuilds the meshes of all dirty chunks and returns their coordinates.
+    pub fn update_meshes(&mut self) -> Vec<ChunkCoords> {
+        self.update_meshes_where(|_| true)
+    }
+
+    /// Like `update_meshes`, but only rebuilds chunks that intersect `frustum`. The rest stay dirty.
+    pub fn update_meshes_in(&mut self, frustum: &Frustum) -> Vec<ChunkCoords> {
+        self.update_meshes_where(|aabb| frustum.intersects_aabb(aabb))
+    }
+
+    fn update_meshes_where<F: Fn(&Aabb) -> bool>(&mut self, f: F) -> Vec<ChunkCoords> {
+        let dirty = self
+            .chunks
+            .iter()
+            .filter(|(&coords, chunk)| chunk.is_dirty() && f(&self.chunk_aabb(coords)))
+            .map(|(&coords, _)| coords)
+            .collect::<Vec<_>>();
+        let meshes = dirty
+            .iter()
+            .map(|&coords| self.chunks[&coords].vtx_data(&self.neighbors(coords)))
+            .collect::<Vec<_>>();
+        for (coords, mesh) in dirty.iter().zip(meshes) {
+            self.chunks.get_mut(coords).unwrap().set_mesh(mesh);
+        }
+        dirty
+    }
+
+    /// Coordinates of the loaded chunks that intersect `frustum`, sorted.
+    pub fn visible_chunks(&self, frustum: &Frustum) -> Vec<ChunkCoords> {
+        let mut visible = self
+            .chunks
+            .keys()
+            .cloned()
+            .filter(|&coords| frustum.intersects_aabb(&self.chunk_aabb(coords)))
+            .collect::<Vec<_>>();
+        visible.sort();
+        visible
+    }
+
+    pub fn chunks(&self) -> hash_map::Iter<'_, ChunkCoords, Chunk> {
+        self.chunks.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Returns the loaded chunk adjacent to the chunk at `coords` across `face`.
+    pub fn neighbor(&self, coords: ChunkCoords, face: Face) -> Option<&Chunk> {
+        let (dx, dy, dz) = face.offset();
+        self.chunk((coords.0 + dx, coords.1 + dy, coords.2 + dz))
+    }
+
+    /// Returns all loaded chunks adjacent to the chunk at `coords`.
+    pub fn neighbors(&self, coords: ChunkCoords) -> ChunkNeighbors<'_> {
+        let mut neighbors = [None; 6];
+        for &face in &Face::ALL {
+            neighbors[face as usize] = self.neighbor(coords, face);
+        }
+        neighbors
+    }
+
+    /// Returns the block at `pos`, or `None` if there is no block or its chunk isn't loaded.
+    pub fn get(&self, pos: &Point3f) -> Option<BlockType> {
+        self.chunk(self.chunk_coords(pos))
+            .and_then(|chunk| chunk[Point3f::new(pos.x.round(), pos.y.round(), pos.z.round())])
+    }
+
+    /// Sets the block at `pos`, loading its chunk if needed. Clearing a block in a chunk that isn't
+    /// loaded does nothing. If the block lies on the chunk's border, the neighboring chunk's mesh
+    /// is also marked dirty.
+    pub fn set(&mut self, pos: &Point3f, block: Option<BlockType>) {
+        let coords = self.chunk_coords(pos);
+        if block.is_none() && self.chunk(coords).is_none() {
+            return;
+        }
+        let half = (self.chunk_side_len / 2) as i32;
+        let chunk = self.load_chunk(coords);
+        let (x, y, z) = chunk.local_coords(pos);
+        if chunk[(x, y, z)] == block {
+            return;
+        }
+        chunk.set((x, y, z), block);
+
+        for face in &Face::ALL {
+            let (dx, dy, dz) = face.offset();
+            if x * dx == half || y * dy == half || z * dz == half {
+                if let Some(neighbor) =
+                    self.chunk_mut((coords.0 + dx, coords.1 + dy, coords.2 + dz))
+                {
+                    neighbor.mark_dirty();
+                }
+            }
+        }
+    }
+
+    pub fn insert(&mut self, pos: &Point3f, block_type: BlockType) {
+        self.set(pos, Some(block_type));
+    }
+
+    /// Returns the block adjacent to the block at `pos` across `face`.
+    pub fn block_neighbor(&self, pos: &Point3f, face: Face) -> Option<BlockType> {
+        self.get(&(pos + face.normal()))
+    }
+
+    /// Returns the position of the closest block intersected by `ray` over all loaded chunks.
+    pub fn intersected_block(&self, ray: &Ray) -> Option<Point3f> {
+        self.closest_block(ray).map(|(_, pos)| pos)
+    }
+
+    /// Like `intersected_block`, but also returns the distance along `ray`.
+    pub fn closest_block(&self, ray: &Ray) -> Option<(f32, Point3f)> {
+        self.chunks
+            .values()
+            .filter_map(|chunk| chunk.closest_block(ray))
+            .min_by(|(t1, _), (t2, _)| t1.partial_cmp(t2).unwrap())
+    }
+
+    /// Returns the first block hit by `ray` no farther than `reach` along it.
+    pub fn raycast(&self, ray: &Ray, reach: f32) -> Option<VoxelHit> {
+        ray.cast_voxels(reach, |pos| self.get(pos).is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block::TextureId, camera::Camera};
+
+    fn cobblestone() -> BlockType {
+        BlockType::named("cobblestone")
+    }
+
+    fn sandstone() -> BlockType {
+        BlockType::named("sandstone")
+    }
+
+    /// Texture of every face of `block`
+    fn texture(block: BlockType) -> TextureId {
+        block.def().texture_id(Face::Top)
+    }
+
+    #[test]
+    fn test_chunk_coords() {
+        let chunk_map = ChunkMap::new(5);
+        assert_eq!(chunk_map.chunk_coords(&Point3f::new(0.0, 0.0, 0.0)), (0, 0, 0));
+        assert_eq!(chunk_map.chunk_coords(&Point3f::new(2.0, -2.0, 2.0)), (0, 0, 0));
+        assert_eq!(chunk_map.chunk_coords(&Point3f::new(3.0, -3.0, 0.0)), (1, -1, 0));
+        assert_eq!(chunk_map.chunk_coords(&Point3f::new(7.0, -7.0, 8.0)), (1, -1, 2));
+        assert_eq!(chunk_map.chunk_coords(&Point3f::new(-8.0, 12.0, 13.0)), (-2, 2, 3));
+        assert_eq!(
+            chunk_map.chunk_center((-2, 2, 3)),
+            Point3f::new(-10.0, 10.0, 15.0)
+        );
+    }
+
+    #[test]
+    fn test_get_set() {
+        let mut chunk_map = ChunkMap::new(3);
+        let pos = Point3f::new(2.0, -4.0, 1.0);
+
+        assert_eq!(chunk_map.get(&pos), None);
+        chunk_map.insert(&pos, cobblestone());
+        assert_eq!(chunk_map.get(&pos), Some(cobblestone()));
+        assert_eq!(chunk_map.len(), 1);
+        assert_eq!(
+            chunk_map.chunk((1, -1, 0)).unwrap()[(-1, -1, 1)],
+            Some(cobblestone())
+        );
+
+        chunk_map.set(&pos, None);
+        assert_eq!(chunk_map.get(&pos), None);
+
+        // Clearing a block doesn't load its chunk
+        chunk_map.update_meshes();
+        chunk_map.set(&Point3f::new(2.0, -1.0, 1.0), None);
+        assert!(chunk_map.chunk((1, 0, 0)).is_none());
+        assert!(chunk_map.update_meshes().is_empty());
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let mut chunk_map = ChunkMap::new(3);
+        let pos1 = Point3f::new(1.0, 0.0, 0.0);
+        let pos2 = Point3f::new(2.0, 0.0, 0.0);
+        chunk_map.insert(&pos1, cobblestone());
+        chunk_map.insert(&pos2, sandstone());
+
+        assert_eq!(
+            chunk_map.block_neighbor(&pos1, Face::Right),
+            Some(sandstone())
+        );
+        assert_eq!(
+            chunk_map.block_neighbor(&pos2, Face::Left),
+            Some(cobblestone())
+        );
+        assert_eq!(chunk_map.block_neighbor(&pos1, Face::Top), None);
+        assert_eq!(
+            chunk_map.neighbor((0, 0, 0), Face::Right).unwrap().center(),
+            Point3f::new(3.0, 0.0, 0.0)
+        );
+        assert!(chunk_map.neighbor((0, 0, 0), Face::Left).is_none());
+    }
+
+    #[test]
+    fn test_intersected_block() {
+        let mut chunk_map = ChunkMap::new(3);
+        let near = Point3f::new(0.0, 0.0, 4.0);
+        let far = Point3f::new(0.0, 0.0, -4.0);
+        chunk_map.insert(&far, cobblestone());
+        chunk_map.insert(&near, cobblestone());
+
+        let ray = Ray::new(Point3f::new(0.0, 0.0, 10.0), -Vector3f::z());
+        assert_eq!(chunk_map.intersected_block(&ray), Some(near));
+        let ray = Ray::new(Point3f::new(0.0, 0.0, -10.0), Vector3f::z());
+        assert_eq!(chunk_map.intersected_block(&ray), Some(far));
+    }
+
+    #[test]
+    fn test_raycast() {
+        let mut chunk_map = ChunkMap::new(3);
+        let near = Point3f::new(1.0, 0.0, 4.0);
+        let far = Point3f::new(1.0, 0.0, -4.0);
+        chunk_map.insert(&far, cobblestone());
+        chunk_map.insert(&near, cobblestone());
+
+        let ray = Ray::new(Point3f::new(1.0, 0.0, 10.0), -Vector3f::z());
+        let hit = chunk_map.raycast(&ray, 20.0).unwrap();
+        assert_eq!(hit.pos, near);
+        assert_eq!(hit.face, Face::Front);
+        assert_eq!(hit.t, 5.5);
+        assert_eq!(chunk_map.raycast(&ray, 5.0), None);
+
+        // Passes through unloaded chunks
+        let ray = Ray::new(Point3f::new(1.0, 10.0, -4.0), -Vector3f::y());
+        let hit = chunk_map.raycast(&ray, 20.0).unwrap();
+        assert_eq!(hit.pos, far);
+        assert_eq!(hit.face, Face::Top);
+    }
+
+    #[test]
+    fn test_update_meshes() {
+        let mut chunk_map = ChunkMap::new(3);
+        chunk_map.insert(&Point3f::new(0.0, 0.0, 0.0), cobblestone());
+        chunk_map.insert(&Point3f::new(6.0, 0.0, 0.0), cobblestone());
+        assert_eq!(chunk_map.update_meshes().len(), 2);
+        assert!(chunk_map.update_meshes().is_empty());
+
+        // Setting a block to its current value doesn't dirty anything
+        chunk_map.insert(&Point3f::new(0.0, 0.0, 0.0), cobblestone());
+        assert!(chunk_map.update_meshes().is_empty());
+
+        chunk_map.insert(&Point3f::new(0.0, 1.0, 0.0), sandstone());
+        assert_eq!(chunk_map.update_meshes(), vec![(0, 0, 0)]);
+        assert_eq!(
+            chunk_map
+                .chunk((0, 0, 0))
+                .unwrap()
+                .mesh()
+                .iter()
+                .filter(|vtx| vtx.layer as TextureId == texture(sandstone()))
+                .count(),
+            5 * 6
+        );
+    }
+
+    #[test]
+    fn test_update_meshes_border() {
+        let mut chunk_map = ChunkMap::new(3);
+        chunk_map.insert(&Point3f::new(0.0, 0.0, 0.0), cobblestone());
+        chunk_map.insert(&Point3f::new(2.0, 0.0, 0.0), cobblestone());
+        chunk_map.update_meshes();
+
+        // (0, 0, 0) isn't on the border of chunk (0, 0, 0)
+        chunk_map.set(&Point3f::new(0.0, 0.0, 0.0), None);
+        assert_eq!(chunk_map.update_meshes(), vec![(0, 0, 0)]);
+
+        // (1, 0, 0) borders chunk (1, 0, 0)
+        chunk_map.insert(&Point3f::new(1.0, 0.0, 0.0), cobblestone());
+        let mut updated = chunk_map.update_meshes();
+        updated.sort();
+        assert_eq!(updated, vec![(0, 0, 0), (1, 0, 0)]);
+        assert_eq!(chunk_map.chunk((1, 0, 0)).unwrap().mesh().len(), 5 * 6);
+    }
+
+    #[test]
+    fn test_load_unload() {
+        let mut chunk_map = ChunkMap::new(3);
+        chunk_map.insert(&Point3f::new(0.0, 0.0, 0.0), cobblestone());
+        chunk_map.update_meshes();
+
+        chunk_map.load_chunk((0, 1, 0));
+        let mut updated = chunk_map.update_meshes();
+        updated.sort();
+        assert_eq!(updated, vec![(0, 0, 0), (0, 1, 0)]);
+
+        assert!(chunk_map.unload_chunk((0, 1, 0)).is_some());
+        assert!(chunk_map.unload_chunk((0, 1, 0)).is_none());
+        assert_eq!(chunk_map.take_unloaded(), vec![(0, 1, 0)]);
+        assert!(chunk_map.take_unloaded().is_empty());
+        assert_eq!(chunk_map.update_meshes(), vec![(0, 0, 0)]);
+    }
+
+    #[test]
+    fn test_culling() {
+        let mut chunk_map = ChunkMap::new(3);
+        for x in -3..=3 {
+            chunk_map.load_chunk((x, 0, -2));
+        }
+        chunk_map.load_chunk((0, 0, 2));
+        assert_eq!(
+            chunk_map.chunk_aabb((0, 0, -2)),
+            Aabb::new(Point3f::new(0.0, 0.0, -6.0), Vector3f::repeat(1.5))
+        );
+
+        // Looks down -z from the origin with a 45 degree field of view
+        let frustum = Camera::new(Point3f::origin(), -Vector3f::z_axis()).frustum(1.0);
+        let visible = vec![(-1, 0, -2), (0, 0, -2), (1, 0, -2)];
+        assert_eq!(chunk_map.visible_chunks(&frustum), visible);
+
+        let mut updated = chunk_map.update_meshes_in(&frustum);
+        updated.sort();
+        assert_eq!(updated, visible);
+        // Chunks out of view are meshed once they're needed
+        let mut updated = chunk_map.update_meshes();
+        updated.sort();
+        assert_eq!(
+            updated,
+            vec![(-3, 0, -2), (-2, 0, -2), (0, 0, 2), (2, 0, -2), (3, 0, -2)]
+        );
+    }
+}
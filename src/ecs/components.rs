@@ -14,6 +14,11 @@ impl Component for TransformComponent {
     type Storage = FlaggedStorage<Self>;
 }
 
+/// An entity's transform at the start of the current tick, used to interpolate its rendered
+/// position between ticks
+#[derive(Component, Debug, Copy, Clone)]
+pub struct PrevTransformComponent(pub Transform3f);
+
 #[derive(Component, Debug, Clone)]
 pub enum PrimitiveGeometryComponent {
     Rectangle(Rectangle),
@@ -30,6 +35,18 @@ impl PrimitiveGeometryComponent {
         }
     }
 
+    /// Vertices grouped by the cube face they belong to, so that each face can be textured
+    /// separately. Flat geometry counts as a top face.
+    pub fn face_vtx_data(&self, transform: &Transform3f) -> Vec<(Face, Vec<Vertex3f>)> {
+        match self {
+            PrimitiveGeometryComponent::UnitCube(cube) => Face::ALL
+                .iter()
+                .map(|&face| (face, cube.face_vtx_data(face, transform)))
+                .collect(),
+            _ => vec![(Face::Top, self.vtx_data(transform))],
+        }
+    }
+
     pub fn geometry(&self) -> &PrimitiveGeometry {
         use self::PrimitiveGeometryComponent::*;
         match self {
@@ -1,16 +1,19 @@
 use crate::{
     ecs::components::{
-        AabbComponent, BlockComponent, PrimitiveGeometryComponent, TransformComponent,
+        AabbComponent, BlockComponent, PrevTransformComponent, PrimitiveGeometryComponent,
+        TransformComponent,
     },
-    game::GameState,
-    geometry::{Ray, UnitCube, PrimitiveGeometry},
-    renderer::{RenderData, Renderer},
+    game::{GameState, TICK_TIME},
+    geometry::{Aabb, PrimitiveGeometry, Ray, UnitCube},
+    input::{Action, Binding},
+    interaction::{break_block, place_block, REACH},
+    player::{MovementMode, EYE_HEIGHT},
+    renderer::{RenderData, Renderer, RendererError},
     types::prelude::*,
-    utils::f32,
+    utils::{f32, transform3f},
 };
-use specs::prelude::*;
+use specs::{prelude::*, world::Index};
 use std::{cell::RefCell, collections::HashMap, ops::DerefMut, rc::Rc};
-use winit::VirtualKeyCode;
 
 const FRAME_TIME_SAMPLE_INTERVAL: f32 = 0.25;
 
@@ -85,71 +88,92 @@ impl<'a> System<'a> for AabbComponentSystem {
     }
 }
 
-pub struct ChunkSystem {
+/// Keeps `GameState::entity_octree` holding every entity with a `TransformComponent` and an
+/// `AabbComponent`, moving entities whose transforms change. Runs after `AabbComponentSystem`.
+pub struct OctreeSystem {
     reader_id: ReaderId<ComponentEvent>,
-    inserted: BitSet,
-    modified: BitSet,
+    dirty: BitSet,
+    /// AABB that each entity in the octree was inserted with, by entity id
+    aabbs: HashMap<Index, (specs::Entity, Aabb)>,
 }
 
-pub struct SelectionSystem;
-
-impl<'a> System<'a> for SelectionSystem {
-    type SystemData = (ReadStorage<'a, AabbComponent>, WriteExpect<'a, GameState>);
-
-    fn run(&mut self, (aabb_storage, mut game_state): Self::SystemData) {
-        let game_state = game_state.deref_mut();
-        let GameState {
-            ref camera,
-            ref mut highlighted,
-            ref chunk,
-            ..
-        } = game_state;
-
-        let ray = Ray::new(camera.pos, camera.direction().into_inner());
-        *highlighted = chunk
-            .intersected_entity(&ray, &aabb_storage)
-            .map(|e| e.entity);
+impl OctreeSystem {
+    pub fn new(reader_id: ReaderId<ComponentEvent>) -> OctreeSystem {
+        OctreeSystem {
+            reader_id,
+            dirty: BitSet::new(),
+            aabbs: HashMap::new(),
+        }
     }
 }
 
-pub struct RenderSystem {
-    pub renderer: Rc<RefCell<Renderer>>,
-}
-
-impl<'a> System<'a> for RenderSystem {
+impl<'a> System<'a> for OctreeSystem {
     type SystemData = (
+        Entities<'a>,
         ReadStorage<'a, TransformComponent>,
-        WriteStorage<'a, PrimitiveGeometryComponent>,
+        ReadStorage<'a, AabbComponent>,
         WriteExpect<'a, GameState>,
-        ReadStorage<'a, BlockComponent>,
     );
 
     fn run(
         &mut self,
-        (transform_storage, mut geometry, mut game_state, block_type_storage): Self::SystemData,
+        (entities, transform_storage, aabb_storage, mut game_state): Self::SystemData,
     ) {
-        let mut renderer = self.renderer.borrow_mut();
+        let octree = &mut game_state.entity_octree;
+        self.dirty.clear();
+
+        // Removals go first, so that an entity reusing a removed entity's id is inserted fresh
+        let mut removed = vec![];
+        for event in transform_storage.channel().read(&mut self.reader_id) {
+            match event {
+                ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) => {
+                    self.dirty.add(*id);
+                }
+                ComponentEvent::Removed(id) => removed.push(*id),
+            }
+        }
+        for id in removed {
+            if let Some((entity, aabb)) = self.aabbs.remove(&id) {
+                octree.remove_with_aabb(entity.into(), &aabb);
+            }
+        }
+
+        for (entity, aabb, _, _) in
+            (&entities, &aabb_storage, &transform_storage, &self.dirty).join()
+        {
+            match self.aabbs.insert(entity.id(), (entity, aabb.0)) {
+                Some((_, old_aabb)) => {
+                    octree.update(entity.into(), &old_aabb, &aabb_storage);
+                }
+                None => octree.insert(entity.into(), &aabb_storage),
+            }
+        }
+    }
+}
+
+pub struct ChunkSystem {
+    reader_id: ReaderId<ComponentEvent>,
+    inserted: BitSet,
+    modified: BitSet,
+}
+
+/// Moves the camera along the current `CameraAnimation`, if any.
+pub struct CameraAnimationSystem;
+
+impl<'a> System<'a> for CameraAnimationSystem {
+    type SystemData = WriteExpect<'a, GameState>;
+
+    fn run(&mut self, mut game_state: Self::SystemData) {
         let game_state = game_state.deref_mut();
         let GameState {
-            ref resized,
             ref mut camera,
-            ref pressed_keys,
-            ref mouse_delta,
-            ref elapsed_time,
-            ref frame_time,
             ref mut camera_animation,
-            ref mut fps_last_sampled_time,
-            ref mut fps_sample,
-            ref highlighted,
-            ref chunk,
+            ref mut player,
+            ref elapsed_time,
             ..
         } = game_state;
 
         let elapsed_time = *elapsed_time;
-        let frame_time = *frame_time;
-
-        let d_yaw = mouse_delta.0 as f32 / 500.0;
-        let d_pitch = mouse_delta.1 as f32 / 500.0;
         let mut camera_animation_finished = false;
         if let Some(camera_animation) = camera_animation {
             // Check if animation has expired
@@ -163,73 +187,615 @@ impl<'a> System<'a> for RenderSystem {
                 camera.pos = pos;
                 camera.pitch_q = pitch_q;
                 camera.yaw_q = yaw_q;
-                camera_animation_finished = false;
             }
-        } else {
-            camera.rotate((-d_yaw, d_pitch));
         }
         if camera_animation_finished {
             *camera_animation = None;
+            // Leave the player where the animation ended
+            player.teleport(camera.pos - Vector3f::new(0.0, EYE_HEIGHT, 0.0));
         }
-        let camera_speed = 3.0 * frame_time;
-        for keycode in pressed_keys.keys() {
-            match keycode {
-                VirtualKeyCode::W => camera.pos += camera_speed * camera.direction().into_inner(),
-                VirtualKeyCode::S => camera.pos -= camera_speed * camera.direction().into_inner(),
-                VirtualKeyCode::A => {
-                    let delta = camera_speed * (Vector3f::cross(&camera.direction(), &camera.up()));
-                    camera.pos -= delta;
+    }
+}
+
+/// Turns the camera with the mouse.
+pub struct CameraControlSystem;
+
+impl<'a> System<'a> for CameraControlSystem {
+    type SystemData = WriteExpect<'a, GameState>;
+
+    fn run(&mut self, mut game_state: Self::SystemData) {
+        let game_state = game_state.deref_mut();
+        let GameState {
+            ref mut camera,
+            ref mouse_delta,
+            ref camera_animation,
+            ..
+        } = game_state;
+
+        if camera_animation.is_none() {
+            let d_yaw = mouse_delta.0 as f32 / 500.0;
+            let d_pitch = mouse_delta.1 as f32 / 500.0;
+            camera.rotate((-d_yaw, d_pitch));
+        }
+    }
+}
+
+/// Remembers each entity's transform at the start of a tick, so that rendering can interpolate
+/// between the last two ticks. Runs before any system that moves entities.
+pub struct TransformSnapshotSystem;
+
+impl<'a> System<'a> for TransformSnapshotSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, TransformComponent>,
+        WriteStorage<'a, PrevTransformComponent>,
+    );
+
+    fn run(&mut self, (entities, transform_storage, mut prev_storage): Self::SystemData) {
+        for (entity, transform) in (&entities, &transform_storage).join() {
+            match prev_storage.get_mut(entity) {
+                Some(prev) => prev.0 = transform.0,
+                None => {
+                    prev_storage
+                        .insert(entity, PrevTransformComponent(transform.0))
+                        .unwrap_or_else(|err| panic!("{:?}", err));
                 }
-                VirtualKeyCode::D => {
-                    let delta = camera_speed * (Vector3f::cross(&camera.direction(), &camera.up()));
-                    camera.pos += delta;
+            }
+        }
+    }
+}
+
+/// Puts the camera at the player's eyes, interpolated between the last two ticks.
+pub struct CameraInterpolationSystem;
+
+impl<'a> System<'a> for CameraInterpolationSystem {
+    type SystemData = WriteExpect<'a, GameState>;
+
+    fn run(&mut self, mut game_state: Self::SystemData) {
+        let game_state = game_state.deref_mut();
+        let GameState {
+            ref mut camera,
+            ref camera_animation,
+            ref player,
+            ref tick_alpha,
+            ..
+        } = game_state;
+
+        if camera_animation.is_none() {
+            camera.pos = player.interpolated_eye(*tick_alpha);
+        }
+    }
+}
+
+/// Runs once per tick, moving the player by `TICK_TIME` according to the movement actions held and
+/// putting the camera at its eyes. `CameraInterpolationSystem` then smooths the camera out between
+/// ticks when rendering.
+#[derive(Default)]
+pub struct MovementSystem {
+    /// Whether `CycleMovementMode` was held last tick
+    cycle_held: bool,
+}
+
+impl<'a> System<'a> for MovementSystem {
+    type SystemData = WriteExpect<'a, GameState>;
+
+    fn run(&mut self, mut game_state: Self::SystemData) {
+        let game_state = game_state.deref_mut();
+        let GameState {
+            ref mut camera,
+            ref input_map,
+            ref pressed_keys,
+            ref pressed_buttons,
+            ref camera_animation,
+            ref mut player,
+            ref movement_speeds,
+            ref chunk_map,
+            ..
+        } = game_state;
+
+        if camera_animation.is_some() {
+            return;
+        }
+
+        let held = |action| input_map.is_held(action, pressed_keys, pressed_buttons);
+        let cycle_held = held(Action::CycleMovementMode);
+        if cycle_held && !self.cycle_held {
+            player.set_mode(player.mode().next());
+        }
+        self.cycle_held = cycle_held;
+
+        let mode = player.mode();
+        // Spectators move where they're looking, everyone else moves horizontally
+        let forward = if mode == MovementMode::Spectator {
+            camera.direction().into_inner()
+        } else {
+            (camera.yaw_q * Vector3f::z_axis()).into_inner()
+        };
+        let right = Vector3f::cross(&camera.direction(), &camera.up()).normalize();
+        let mut movement = Vector3f::zeros();
+        if held(Action::MoveForward) {
+            movement += forward;
+        }
+        if held(Action::MoveBackward) {
+            movement -= forward;
+        }
+        if held(Action::MoveLeft) {
+            movement -= right;
+        }
+        if held(Action::MoveRight) {
+            movement += right;
+        }
+        if mode != MovementMode::Walk {
+            if held(Action::Jump) {
+                movement += camera.up().into_inner();
+            }
+            if held(Action::Crouch) {
+                movement -= camera.up().into_inner();
+            }
+        }
+        if movement != Vector3f::zeros() {
+            movement = movement.normalize() * movement_speeds.speed(mode, held(Action::Sprint));
+        }
+
+        if held(Action::Jump) {
+            player.jump();
+        }
+        player.step(chunk_map, &movement, TICK_TIME);
+        camera.pos = player.eye();
+    }
+}
+
+/// Highlights the closest block or entity within reach that the camera is looking at.
+pub struct SelectionSystem;
+
+impl<'a> System<'a> for SelectionSystem {
+    type SystemData = (ReadStorage<'a, AabbComponent>, WriteExpect<'a, GameState>);
+
+    fn run(&mut self, (aabb_storage, mut game_state): Self::SystemData) {
+        let game_state = game_state.deref_mut();
+        let GameState {
+            ref camera,
+            ref mut highlighted,
+            ref mut highlighted_face,
+            ref mut highlighted_entity,
+            ref chunk_map,
+            ref entity_octree,
+            ..
+        } = game_state;
+
+        let ray = Ray::new(camera.pos, camera.direction().into_inner());
+        let mut hit = chunk_map.raycast(&ray, REACH);
+        *highlighted_entity = None;
+        if let Some((t, entity)) = entity_octree.closest_entity(&ray, &aabb_storage) {
+            let closer = match hit {
+                Some(hit) => t < hit.t,
+                None => t <= REACH,
+            };
+            if closer {
+                *highlighted_entity = Some(entity);
+                hit = None;
+            }
+        }
+        *highlighted = hit.map(|hit| hit.pos);
+        *highlighted_face = hit.map(|hit| hit.face);
+    }
+}
+
+/// Left-clicking breaks the highlighted block or deletes the highlighted entity, and
+/// right-clicking places the selected block against the highlighted face. Blocks can't be placed
+/// against entities.
+pub struct BlockInteractionSystem;
+
+impl<'a> System<'a> for BlockInteractionSystem {
+    type SystemData = (Entities<'a>, WriteExpect<'a, GameState>);
+
+    fn run(&mut self, (entities, mut game_state): Self::SystemData) {
+        let game_state = game_state.deref_mut();
+        let GameState {
+            ref player,
+            ref highlighted,
+            ref highlighted_face,
+            ref highlighted_entity,
+            ref input_map,
+            ref mouse_clicks,
+            ref selected_block,
+            ref mut chunk_map,
+            ..
+        } = game_state;
+
+        // The highlighted block or entity is stale after the first click
+        let click = match mouse_clicks.first() {
+            Some(&button) => Binding::Mouse(button),
+            None => return,
+        };
+        for action in input_map.actions(click) {
+            match action {
+                Action::BreakBlock => {
+                    if let Some(entity) = highlighted_entity {
+                        entities
+                            .delete(entity.entity)
+                            .unwrap_or_else(|err| panic!("{:?}", err));
+                    } else if let Some(pos) = highlighted {
+                        break_block(chunk_map, pos);
+                    }
+                    return;
+                }
+                Action::PlaceBlock => {
+                    if let (Some(pos), Some(face)) = (highlighted, highlighted_face) {
+                        place_block(chunk_map, pos, *face, *selected_block, &player.aabb());
+                    }
+                    return;
                 }
                 _ => (),
             }
         }
+    }
+}
+
+/// Samples the frame rate every `FRAME_TIME_SAMPLE_INTERVAL` seconds.
+pub struct FpsCounterSystem;
+
+impl<'a> System<'a> for FpsCounterSystem {
+    type SystemData = WriteExpect<'a, GameState>;
+
+    fn run(&mut self, mut game_state: Self::SystemData) {
+        let game_state = game_state.deref_mut();
+        let GameState {
+            ref elapsed_time,
+            ref frame_time,
+            ref mut fps_last_sampled_time,
+            ref mut fps_sample,
+            ..
+        } = game_state;
+
+        if *elapsed_time >= *fps_last_sampled_time + FRAME_TIME_SAMPLE_INTERVAL {
+            *fps_last_sampled_time = *elapsed_time;
+            *fps_sample = 1.0 / frame_time;
+        }
+    }
+}
+
+/// Collects the vertex data of the world and draws it.
+pub struct RenderSystem {
+    pub renderer: Rc<RefCell<Renderer>>,
+    /// Set if drawing fails, for whoever runs the system to take and handle
+    pub error: Rc<RefCell<Option<RendererError>>>,
+}
+
+impl<'a> System<'a> for RenderSystem {
+    type SystemData = (
+        ReadStorage<'a, TransformComponent>,
+        ReadStorage<'a, PrevTransformComponent>,
+        WriteStorage<'a, PrimitiveGeometryComponent>,
+        WriteExpect<'a, GameState>,
+        ReadStorage<'a, BlockComponent>,
+        ReadStorage<'a, AabbComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            transform_storage,
+            prev_transform_storage,
+            mut geometry,
+            mut game_state,
+            block_type_storage,
+            aabb_storage,
+        ) = data;
+        let mut renderer = self.renderer.borrow_mut();
+        let game_state = game_state.deref_mut();
+        let frustum = game_state.camera.frustum(game_state.aspect());
+        let GameState {
+            ref resized,
+            ref fps_sample,
+            ref highlighted,
+            ref highlighted_entity,
+            ref tick_alpha,
+            ref mut chunk_map,
+            ..
+        } = game_state;
 
-        let mut vertices = HashMap::new();
-        for (transform, geometry, block_type_component) in
-            (&transform_storage, &mut geometry, &block_type_storage).join()
+        let mut vertices = vec![];
+        for (transform, prev_transform, geometry, block_type_component, aabb) in (
+            &transform_storage,
+            prev_transform_storage.maybe(),
+            &mut geometry,
+            &block_type_storage,
+            aabb_storage.maybe(),
+        )
+            .join()
         {
-            vertices
-                .entry(block_type_component.0)
-                .or_insert_with(|| vec![])
-                .extend(geometry.vtx_data(&transform.0));
+            if let Some(AabbComponent(aabb)) = aabb {
+                if !frustum.intersects_aabb(aabb) {
+                    continue;
+                }
+            }
+            let transform = match prev_transform {
+                Some(prev) => transform3f::clerp(&prev.0, &transform.0, *tick_alpha),
+                None => transform.0,
+            };
+            let def = block_type_component.0.def();
+            for (face, face_vertices) in geometry.face_vtx_data(&transform) {
+                let layer = def.texture_id(face) as u32;
+                vertices.extend(face_vertices.into_iter().map(|vtx| vtx.with_layer(layer)));
+            }
         }
 
-        for (block_type, vtxs) in chunk.vtx_data(&transform_storage, &block_type_storage) {
-            vertices
-                .entry(block_type)
-                .or_insert_with(|| vec![])
-                .extend(vtxs);
+        let mut chunk_meshes = HashMap::new();
+        for coords in chunk_map.take_unloaded() {
+            chunk_meshes.insert(coords, None);
         }
+        for coords in chunk_map.update_meshes_in(&frustum) {
+            let mesh = chunk_map.chunk(coords).unwrap().mesh().clone();
+            chunk_meshes.insert(coords, Some(mesh));
+        }
+        let visible_chunks = chunk_map.visible_chunks(&frustum);
 
         let selection_vertices = if let Some(highlighted) = highlighted {
-            let transform = transform_storage.get(*highlighted).unwrap();
             let cube = UnitCube::new(1.0);
-            Some(cube.vtx_data(&transform.0))
+            Some(cube.vtx_data(&Transform3f::new_with_translation(highlighted.coords)))
+        } else if let Some(aabb) = highlighted_entity.and_then(|e| aabb_storage.get(e.entity)) {
+            // Outline the entity's bounding box
+            let transform = Matrix4f::new_translation(&aabb.0.center.coords)
+                * Matrix4f::new_nonuniform_scaling(&(2.0 * aabb.0.extents));
+            let cube = UnitCube::new(1.0);
+            Some(cube.vtx_data(&Transform3f::from_matrix_unchecked(transform)))
         } else {
             None
         };
 
-        if elapsed_time >= *fps_last_sampled_time + FRAME_TIME_SAMPLE_INTERVAL {
-            *fps_last_sampled_time = elapsed_time;
-            *fps_sample = 1.0 / frame_time;
+        let fps = *fps_sample;
+
+        let result = renderer.draw_frame(
+            &game_state,
+            &RenderData {
+                vertices,
+                chunk_meshes,
+                visible_chunks,
+                fps,
+                selection_vertices,
+            },
+            *resized,
+        );
+        if let Err(err) = result {
+            *self.error.borrow_mut() = Some(err);
         }
+    }
+}
 
-        let fps = *fps_sample;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::BlockType,
+        camera::{Camera, CameraAnimation},
+        chunk_map::ChunkMap,
+        ecs::entity::Entity,
+        input::InputMap,
+        player::Player,
+        region::RegionStore,
+    };
+    use winit::VirtualKeyCode;
+
+    fn world() -> World {
+        let mut state = GameState::new(
+            Player::new(Point3f::new(0.0, 0.5, 0.0)),
+            InputMap::default(),
+            RegionStore::new("saves/test"),
+        );
+        let mut chunk_map = ChunkMap::new(9);
+        for x in -4..=4 {
+            for z in -4..=4 {
+                chunk_map.insert(
+                    &Point3f::new(x as f32, 0.0, z as f32),
+                    BlockType::named("stone"),
+                );
+            }
+        }
+        state.chunk_map = chunk_map;
+        let mut world = World::new();
+        world.add_resource(state);
+        world
+    }
+
+    #[test]
+    fn test_fps_counter() {
+        let world = world();
+        {
+            let mut state = world.write_resource::<GameState>();
+            state.elapsed_time = 1.0;
+            state.frame_time = 0.02;
+        }
+        FpsCounterSystem.run_now(&world.res);
+        let state = world.read_resource::<GameState>();
+        assert_eq!(state.fps_sample, 50.0);
+        assert_eq!(state.fps_last_sampled_time, 1.0);
+    }
 
-        renderer
-            .draw_frame(
-                &game_state,
-                &RenderData {
-                    vertices,
-                    fps,
-                    selection_vertices,
-                },
-                *resized,
+    #[test]
+    fn test_camera_animation() {
+        let world = world();
+        let end_pos = Point3f::new(2.0, 3.0, 1.0);
+        {
+            let mut state = world.write_resource::<GameState>();
+            let animation = CameraAnimation::new(
+                &state.camera,
+                end_pos,
+                &Vector3f::new(1.0, 0.0, 0.0),
+                0.0,
+                1.0,
+            );
+            state.camera_animation = Some(animation);
+            state.elapsed_time = 0.5;
+            state.mouse_delta = (100.0, 0.0);
+        }
+        CameraAnimationSystem.run_now(&world.res);
+        // Mouse look is ignored during the animation
+        let direction = world.read_resource::<GameState>().camera.direction();
+        CameraControlSystem.run_now(&world.res);
+        {
+            let state = world.read_resource::<GameState>();
+            assert!(state.camera_animation.is_some());
+            assert_eq!(state.camera.direction(), direction);
+        }
+
+        world.write_resource::<GameState>().elapsed_time = 1.5;
+        CameraAnimationSystem.run_now(&world.res);
+        let state = world.read_resource::<GameState>();
+        assert!(state.camera_animation.is_none());
+        assert_eq!(state.camera.pos, end_pos);
+        assert!(state.player.eye().almost_eq(&end_pos));
+    }
+
+    #[test]
+    fn test_movement() {
+        let world = world();
+        {
+            let mut state = world.write_resource::<GameState>();
+            state.pressed_keys.insert(VirtualKeyCode::W, 1);
+        }
+        let mut system = MovementSystem::default();
+        for _ in 0..30 {
+            system.run_now(&world.res);
+        }
+        let state = world.read_resource::<GameState>();
+        // The camera starts out looking towards -z
+        assert!(state.player.pos.z < -1.0);
+        assert!(state.player.pos.x.almost_eq(0.0));
+        assert!(state.player.on_ground);
+        assert_eq!(state.camera.pos, state.player.eye());
+    }
+
+    #[test]
+    fn test_camera_interpolation() {
+        let world = world();
+        {
+            let mut state = world.write_resource::<GameState>();
+            state.player.prev_pos = Point3f::new(0.0, 0.5, 0.0);
+            state.player.pos = Point3f::new(1.0, 0.5, 0.0);
+            state.tick_alpha = 0.25;
+        }
+        CameraInterpolationSystem.run_now(&world.res);
+        let state = world.read_resource::<GameState>();
+        assert!(state
+            .camera
+            .pos
+            .almost_eq(&Point3f::new(0.25, 0.5 + EYE_HEIGHT, 0.0)));
+    }
+
+    #[test]
+    fn test_octree_selection() {
+        let mut world = world();
+        world.register::<TransformComponent>();
+        world.register::<PrimitiveGeometryComponent>();
+        world.register::<AabbComponent>();
+        let (mut aabb_system, mut octree_system) = {
+            let mut transform_storage = world.write_storage::<TransformComponent>();
+            (
+                AabbComponentSystem::new(
+                    transform_storage.register_reader(),
+                    BitSet::new(),
+                    BitSet::new(),
+                ),
+                OctreeSystem::new(transform_storage.register_reader()),
             )
-            .expect("draw_frame()");
+        };
+        let mut run = |world: &mut World| {
+            world.maintain();
+            aabb_system.run_now(&world.res);
+            octree_system.run_now(&world.res);
+            SelectionSystem.run_now(&world.res);
+        };
+
+        {
+            let mut state = world.write_resource::<GameState>();
+            let eye = state.player.eye();
+            state.camera = Camera::new_with_target(eye, eye + Vector3f::new(0.0, -1.0, -1.0));
+        }
+        // Nothing in the way of the floor
+        run(&mut world);
+        assert!(world.read_resource::<GameState>().highlighted.is_some());
+
+        // A cube between the camera and the floor
+        let (eye, direction) = {
+            let camera = &world.read_resource::<GameState>().camera;
+            (camera.pos, camera.direction().into_inner())
+        };
+        let entity = world
+            .create_entity()
+            .with(TransformComponent(Transform3f::new_with_translation(
+                (eye + direction).coords,
+            )))
+            .with(PrimitiveGeometryComponent::UnitCube(UnitCube::new(0.5)))
+            .build();
+        run(&mut world);
+        {
+            let state = world.read_resource::<GameState>();
+            assert_eq!(state.entity_octree.len(), 1);
+            assert_eq!(state.highlighted_entity, Some(Entity::from(entity)));
+            assert_eq!(state.highlighted, None);
+            assert_eq!(state.highlighted_face, None);
+        }
+
+        // Moved out of view
+        world
+            .write_storage::<TransformComponent>()
+            .get_mut(entity)
+            .unwrap()
+            .0 = Transform3f::new_with_translation(Vector3f::new(0.0, 10.0, 0.0));
+        run(&mut world);
+        {
+            let state = world.read_resource::<GameState>();
+            assert_eq!(state.entity_octree.len(), 1);
+            assert_eq!(state.highlighted_entity, None);
+            assert!(state.highlighted.is_some());
+            let ray = Ray::new(eye, Vector3f::y());
+            assert_eq!(
+                state
+                    .entity_octree
+                    .intersect_entity(&ray, &world.read_storage()),
+                Some(Entity::from(entity))
+            );
+        }
+
+        world.delete_entity(entity).unwrap();
+        run(&mut world);
+        assert!(world.read_resource::<GameState>().entity_octree.is_empty());
+    }
+
+    #[test]
+    fn test_transform_snapshot() {
+        let mut world = world();
+        world.register::<TransformComponent>();
+        world.register::<PrevTransformComponent>();
+        let entity = world
+            .create_entity()
+            .with(TransformComponent(Transform3f::identity()))
+            .build();
+        TransformSnapshotSystem.run_now(&world.res);
+
+        // A tick moves the entity
+        let moved = Transform3f::new_with_translation(Vector3f::new(1.0, 0.0, 0.0));
+        world
+            .write_storage::<TransformComponent>()
+            .get_mut(entity)
+            .unwrap()
+            .0 = moved;
+        let prev = world
+            .read_storage::<PrevTransformComponent>()
+            .get(entity)
+            .unwrap()
+            .0;
+        assert_eq!(prev, Transform3f::identity());
+        let interpolated = transform3f::clerp(&prev, &moved, 0.25);
+        assert!((interpolated * Point3f::origin()).almost_eq(&Point3f::new(0.25, 0.0, 0.0)));
+
+        // The next tick starts from where the last one ended
+        TransformSnapshotSystem.run_now(&world.res);
+        let prev = world
+            .read_storage::<PrevTransformComponent>()
+            .get(entity)
+            .unwrap()
+            .0;
+        assert_eq!(prev, moved);
     }
 }
@@ -1,28 +1,91 @@
+use crate::platform::PlatformEvent;
 use std::collections::HashMap;
-use winit::{DeviceEvent, ElementState, KeyboardInput, VirtualKeyCode};
+use winit::{MouseButton, VirtualKeyCode};
 
-pub fn on_device_event(
-    event: &DeviceEvent,
+pub fn on_input_event(
+    event: &PlatformEvent,
     pressed_keys: &mut HashMap<VirtualKeyCode, usize>,
+    pressed_buttons: &mut HashMap<MouseButton, usize>,
+    mouse_clicks: &mut Vec<MouseButton>,
     (delta_x, delta_y): &mut (f64, f64),
+    wheel_delta: &mut f32,
 ) {
-    match event {
-        DeviceEvent::Key(KeyboardInput {
-            virtual_keycode: Some(keycode),
-            state,
-            ..
-        }) => match state {
-            ElementState::Pressed => {
-                *pressed_keys.entry(*keycode).or_insert(0) += 1;
+    match *event {
+        PlatformEvent::Key { keycode, pressed } => {
+            if pressed {
+                *pressed_keys.entry(keycode).or_insert(0) += 1;
+            } else {
+                pressed_keys.remove(&keycode);
             }
-            ElementState::Released => {
-                pressed_keys.remove(keycode);
+        }
+        PlatformEvent::MouseButton { button, pressed } => {
+            if pressed {
+                *pressed_buttons.entry(button).or_insert(0) += 1;
+                mouse_clicks.push(button);
+            } else {
+                pressed_buttons.remove(&button);
             }
-        },
-        DeviceEvent::MouseMotion { delta: (dx, dy) } => {
+        }
+        PlatformEvent::MouseMotion { dx, dy } => {
             *delta_x += dx;
             *delta_y += dy;
         }
+        PlatformEvent::MouseWheel { lines } => *wheel_delta += lines,
         _ => (),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mouse_events() {
+        let mut pressed_keys = HashMap::new();
+        let mut pressed_buttons = HashMap::new();
+        let mut mouse_clicks = vec![];
+        let mut mouse_delta = (0.0, 0.0);
+        let mut wheel_delta = 0.0;
+        let mut handle = |event| {
+            on_input_event(
+                &event,
+                &mut pressed_keys,
+                &mut pressed_buttons,
+                &mut mouse_clicks,
+                &mut mouse_delta,
+                &mut wheel_delta,
+            )
+        };
+
+        handle(PlatformEvent::MouseButton {
+            button: MouseButton::Left,
+            pressed: true,
+        });
+        handle(PlatformEvent::MouseButton {
+            button: MouseButton::Right,
+            pressed: true,
+        });
+        handle(PlatformEvent::MouseButton {
+            button: MouseButton::Right,
+            pressed: false,
+        });
+        handle(PlatformEvent::MouseButton {
+            button: MouseButton::Right,
+            pressed: true,
+        });
+        handle(PlatformEvent::MouseWheel { lines: -1.0 });
+        handle(PlatformEvent::MouseWheel { lines: 2.0 });
+        handle(PlatformEvent::MouseMotion { dx: 3.0, dy: -1.0 });
+
+        assert_eq!(pressed_buttons.get(&MouseButton::Left), Some(&1));
+        assert_eq!(pressed_buttons.get(&MouseButton::Right), Some(&1));
+        assert_eq!(pressed_buttons.get(&MouseButton::Middle), None);
+        assert_eq!(
+            mouse_clicks,
+            vec![MouseButton::Left, MouseButton::Right, MouseButton::Right]
+        );
+        assert_eq!(wheel_delta, 1.0);
+        assert!(pressed_keys.is_empty());
+        assert_eq!(mouse_delta, (3.0, -1.0));
+    }
+}
@@ -0,0 +1,48 @@
+use crate::platform::PlatformEvent;
+use std::collections::VecDeque;
+
+/// Where the game gets input and window events from.
+pub trait EventSource {
+    /// Calls `callback` with each event that arrived since the last call.
+    fn poll_events(&mut self, callback: &mut dyn FnMut(PlatformEvent));
+}
+
+/// Replays a fixed list of events, one frame's worth per poll. Once it runs out, polls return no
+/// events.
+#[derive(Debug, Default)]
+pub struct ScriptedEvents {
+    frames: VecDeque<Vec<PlatformEvent>>,
+}
+
+impl ScriptedEvents {
+    /// `frames[i]` holds the events returned by the `i`th poll.
+    pub fn new(frames: Vec<Vec<PlatformEvent>>) -> ScriptedEvents {
+        ScriptedEvents {
+            frames: frames.into_iter().collect(),
+        }
+    }
+
+    /// Adds events to be returned after all the ones already queued.
+    pub fn push_frame(&mut self, events: Vec<PlatformEvent>) {
+        self.frames.push_back(events);
+    }
+
+    /// Number of polls left that return events
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+impl EventSource for ScriptedEvents {
+    fn poll_events(&mut self, callback: &mut dyn FnMut(PlatformEvent)) {
+        if let Some(events) = self.frames.pop_front() {
+            for event in events {
+                callback(event);
+            }
+        }
+    }
+}
@@ -1,35 +1,72 @@
 use crate::{
     block::BlockType,
     camera::{Camera, CameraAnimation},
-    chunk::Chunk,
+    chunk::CHUNK_SIDE_LEN,
+    chunk_map::{ChunkCoords, ChunkMap},
     ecs::{
-        entity, AabbComponent, AabbComponentSystem, BlockComponent, PrimitiveGeometryComponent,
-        RenderSystem, SelectionSystem, TransformComponent,
+        entity::Entity, AabbComponent, AabbComponentSystem, BlockComponent, BlockInteractionSystem,
+        CameraAnimationSystem, CameraControlSystem, CameraInterpolationSystem, FpsCounterSystem,
+        MovementSystem, OctreeSystem, PrevTransformComponent, PrimitiveGeometryComponent,
+        RenderSystem, SelectionSystem, TransformComponent, TransformSnapshotSystem,
     },
-    event_handlers::on_device_event,
-    geometry::Square,
-    na::Translation3,
-    renderer::Renderer,
+    event_handlers::on_input_event,
+    geometry::{Aabb, UnitCube},
+    input::{Action, InputMap, INPUT_MAP_PATH},
+    octree::Node,
+    platform::{Platform, PlatformEvent, WinitPlatform},
+    player::{MovementSpeeds, Player, MOVEMENT_SPEEDS_PATH},
+    region::RegionStore,
+    renderer::{Renderer, RendererError},
     types::prelude::*,
     utils::NSEC_PER_SEC,
     vulkan::VulkanApp,
+    worldgen::WorldGenerator,
 };
-use alga::general::SubsetOf;
 use failure::{err_msg, Error};
-use specs::prelude::*;
+use specs::{
+    prelude::*,
+    shred::{Fetch, FetchMut},
+};
 use std::{
-    cell::{RefCell, RefMut},
-    collections::HashMap,
-    ops::DerefMut,
-    rc::Rc,
-    time::Instant,
+    cell::RefCell, collections::HashMap, ops::DerefMut, path::PathBuf, rc::Rc, time::Instant,
 };
-use winit::{dpi::LogicalSize, Event, VirtualKeyCode, WindowEvent};
+use winit::{MouseButton, VirtualKeyCode};
+
+/// Seed used to generate the world
+const WORLD_SEED: u64 = 0;
+/// Chunks within this many chunks of the player's chunk along x and z are kept loaded
+const WORLD_RADIUS: i32 = 2;
+/// Chunks within this many chunks of the player's chunk along y are kept loaded
+const VERTICAL_RADIUS: i32 = 1;
+/// Chunks are only unloaded once they're this many chunks beyond the radius, so that walking back
+/// and forth across a chunk border doesn't save and reload the same chunks over and over.
+const UNLOAD_MARGIN: i32 = 1;
+/// Directory that chunks are saved to
+const SAVE_DIR: &str = "saves/world";
+/// Length of one simulation tick, in seconds
+pub const TICK_TIME: f32 = 1.0 / 60.0;
+/// Most ticks run per frame. Time beyond this is dropped, so that a slow frame doesn't make the
+/// next one even slower.
+const MAX_TICKS_PER_FRAME: u32 = 10;
+/// Default size of the window
+const SCREEN_SIZE: (u32, u32) = (1024, 768);
+/// Half the side length of the entity octree's root, which is centered at the origin. Entities
+/// outside of it are kept in the root.
+const ENTITY_OCTREE_EXTENT: f32 = 256.0;
+/// Where a floating block entity is spawned, relative to the ground at the origin. Unlike blocks
+/// in chunks, it isn't saved, so it comes back on every start.
+const MARKER_OFFSET: (f32, f32, f32) = (0.0, 3.5, -4.0);
 
 pub struct GameState {
-    pub resized: bool,
+    /// New size of the window if it was resized since the last frame
+    pub resized: Option<(u32, u32)>,
+    /// Size of the window, in pixels
+    pub screen_size: (u32, u32),
 
     pub camera: Camera,
+    pub player: Player,
+    pub movement_speeds: MovementSpeeds,
+    pub input_map: InputMap,
     pub pressed_keys: HashMap<VirtualKeyCode, usize>,
     /// In pixels?
     pub mouse_delta: (f64, f64),
@@ -37,24 +74,234 @@ pub struct GameState {
     pub elapsed_time: f32,
     /// In seconds
     pub frame_time: f32,
+    /// Fraction of a tick elapsed since the last tick ran, used to interpolate rendering
+    pub tick_alpha: f32,
     /// In seconds
     pub fps_last_sampled_time: f32,
     /// In frames per second
     pub fps_sample: f32,
     pub camera_animation: Option<CameraAnimation>,
-    pub highlighted: Option<Entity>,
-    pub chunk: Chunk,
+    /// Position of the block the camera is looking at
+    pub highlighted: Option<Point3f>,
+    /// Face of the highlighted block that the camera is looking at
+    pub highlighted_face: Option<Face>,
+    /// Entity the camera is looking at, if it's closer than any block
+    pub highlighted_entity: Option<Entity>,
+    /// Number of press events seen for each mouse button that is held down
+    pub pressed_buttons: HashMap<MouseButton, usize>,
+    /// Mouse buttons clicked since the last frame, in order
+    pub mouse_clicks: Vec<MouseButton>,
+    /// Lines scrolled by the mouse wheel since the last frame. Positive is away from the user.
+    pub wheel_delta: f32,
+    /// Block type placed by right-clicking
+    pub selected_block: BlockType,
+    pub chunk_map: ChunkMap,
+    /// All entities with an `AabbComponent`, kept up to date by `OctreeSystem`
+    pub entity_octree: Node,
+    pub region_store: RegionStore,
+}
+
+impl GameState {
+    /// Creates a state with no chunks loaded and the camera at `player`'s eyes.
+    pub fn new(player: Player, input_map: InputMap, region_store: RegionStore) -> GameState {
+        let eye = player.eye();
+        let camera = Camera::new_with_target(eye, eye + Vector3f::new(0.0, -1.0, -3.0));
+        GameState {
+            resized: None,
+            screen_size: SCREEN_SIZE,
+            camera,
+            player,
+            movement_speeds: MovementSpeeds::default(),
+            input_map,
+            pressed_keys: HashMap::new(),
+            mouse_delta: (0.0, 0.0),
+            elapsed_time: 0.0,
+            frame_time: 0.0,
+            tick_alpha: 0.0,
+            fps_sample: 0.0,
+            fps_last_sampled_time: 0.0,
+            camera_animation: None,
+            highlighted: None,
+            highlighted_face: None,
+            highlighted_entity: None,
+            pressed_buttons: HashMap::new(),
+            mouse_clicks: vec![],
+            wheel_delta: 0.0,
+            selected_block: BlockType::named("cobblestone"),
+            chunk_map: ChunkMap::new(CHUNK_SIDE_LEN),
+            entity_octree: Node::empty(Aabb::new(
+                Point3f::origin(),
+                Vector3f::repeat(ENTITY_OCTREE_EXTENT),
+            )),
+            region_store,
+        }
+    }
+
+    /// Width of the screen over its height, or 1 if the window has no area.
+    pub fn aspect(&self) -> f32 {
+        match self.screen_size {
+            (width, height) if width > 0 && height > 0 => width as f32 / height as f32,
+            _ => 1.0,
+        }
+    }
+}
+
+/// Builds a `Game`. By default the game opens a window, reads events from it and draws to it with
+/// Vulkan. The platform and renderer can be replaced, e.g. to run the game headless.
+pub struct GameBuilder {
+    screen_size: (u32, u32),
+    platform: Option<Box<dyn Platform>>,
+    renderer: Option<Box<dyn Renderer>>,
+    input_map: Option<InputMap>,
+    movement_speeds: Option<MovementSpeeds>,
+    save_dir: PathBuf,
+    world_radius: i32,
+}
+
+impl Default for GameBuilder {
+    fn default() -> GameBuilder {
+        GameBuilder {
+            screen_size: SCREEN_SIZE,
+            platform: None,
+            renderer: None,
+            input_map: None,
+            movement_speeds: None,
+            save_dir: PathBuf::from(SAVE_DIR),
+            world_radius: WORLD_RADIUS,
+        }
+    }
+}
+
+impl GameBuilder {
+    pub fn new() -> GameBuilder {
+        GameBuilder::default()
+    }
+
+    /// Size of the window. Ignored if a platform is given.
+    pub fn screen_size(mut self, width: u32, height: u32) -> GameBuilder {
+        self.screen_size = (width, height);
+        self
+    }
+
+    pub fn platform(mut self, platform: Box<dyn Platform>) -> GameBuilder {
+        self.platform = Some(platform);
+        self
+    }
+
+    /// If no renderer is given, a `VulkanApp` is created for the platform's window. A platform
+    /// without a window needs one, e.g. a `HeadlessRenderer`.
+    pub fn renderer(mut self, renderer: Box<dyn Renderer>) -> GameBuilder {
+        self.renderer = Some(renderer);
+        self
+    }
+
+    /// Bindings to use instead of the ones in `INPUT_MAP_PATH`
+    pub fn input_map(mut self, input_map: InputMap) -> GameBuilder {
+        self.input_map = Some(input_map);
+        self
+    }
+
+    /// Speeds to use instead of the ones in `MOVEMENT_SPEEDS_PATH`
+    pub fn movement_speeds(mut self, movement_speeds: MovementSpeeds) -> GameBuilder {
+        self.movement_speeds = Some(movement_speeds);
+        self
+    }
+
+    pub fn save_dir<P: Into<PathBuf>>(mut self, save_dir: P) -> GameBuilder {
+        self.save_dir = save_dir.into();
+        self
+    }
+
+    /// Chunks within this many chunks of the origin along x and z are loaded at startup.
+    pub fn world_radius(mut self, world_radius: i32) -> GameBuilder {
+        self.world_radius = world_radius;
+        self
+    }
+
+    pub fn build<'a, 'b>(self) -> Result<Game<'a, 'b>, Error> {
+        let platform = match self.platform {
+            Some(platform) => platform,
+            None => {
+                let (width, height) = self.screen_size;
+                Box::new(WinitPlatform::new("Minecrust", width, height)?)
+            }
+        };
+        let renderer = match (self.renderer, platform.window_handle()) {
+            (Some(renderer), _) => renderer,
+            (None, Some(window)) => {
+                let (width, height) = platform.window_size();
+                Box::new(VulkanApp::new(window, width, height)?)
+            }
+            (None, None) => {
+                return Err(err_msg(
+                    "a renderer is required for a platform without a window",
+                ))
+            }
+        };
+        let input_map = match self.input_map {
+            Some(input_map) => input_map,
+            None => InputMap::load(INPUT_MAP_PATH)?,
+        };
+        let movement_speeds = match self.movement_speeds {
+            Some(movement_speeds) => movement_speeds,
+            None => MovementSpeeds::load(MOVEMENT_SPEEDS_PATH)?,
+        };
+        Game::new_with(
+            platform,
+            renderer,
+            input_map,
+            movement_speeds,
+            RegionStore::new(self.save_dir),
+            self.world_radius,
+        )
+    }
 }
 
 pub struct Game<'a, 'b> {
     world: World,
-    dispatcher: Dispatcher<'a, 'b>,
-    renderer: Rc<RefCell<Renderer>>,
+    /// Systems run once per tick
+    update_dispatcher: Dispatcher<'a, 'b>,
+    /// Systems run once per frame
+    render_dispatcher: Dispatcher<'a, 'b>,
+    /// Declared after the dispatchers, which hold the renderer, so that the window outlives it
+    platform: Box<dyn Platform>,
+
+    focused: bool,
+    should_grab_cursor: bool,
+    cursor_grabbed: bool,
+    already_changed_cursor_state: bool,
+    /// Time not yet simulated, in seconds
+    accumulator: f32,
+    /// Where `RenderSystem` puts the error if drawing a frame fails
+    render_error: Rc<RefCell<Option<RendererError>>>,
+    world_generator: WorldGenerator,
+    world_radius: i32,
+    /// Chunk of the player when chunks were last loaded and unloaded around it
+    streamed_around: ChunkCoords,
 }
 
 impl<'a, 'b> Game<'a, 'b> {
     pub fn new(screen_width: u32, screen_height: u32) -> Result<Game<'a, 'b>, Error> {
-        let camera = Camera::new_with_target(Point3f::new(0.0, 0.0, 3.0), Point3f::origin());
+        GameBuilder::new()
+            .screen_size(screen_width, screen_height)
+            .build()
+    }
+
+    fn new_with(
+        platform: Box<dyn Platform>,
+        renderer: Box<dyn Renderer>,
+        input_map: InputMap,
+        movement_speeds: MovementSpeeds,
+        region_store: RegionStore,
+        world_radius: i32,
+    ) -> Result<Game<'a, 'b>, Error> {
+        let world_generator = WorldGenerator::new(WORLD_SEED);
+        // Stand on top of the terrain at the origin
+        let player = Player::new(Point3f::new(
+            0.0,
+            world_generator.height(0, 0) as f32 + 0.5,
+            0.0,
+        ));
         // let camera_animation = CameraAnimation::new(
         //     &camera,
         //     Point3f::new(1.0, -1.0, 3.0),
@@ -63,32 +310,24 @@ impl<'a, 'b> Game<'a, 'b> {
         //     1.0,
         // );
 
-        let mut state = GameState {
-            resized: false,
-            camera,
-            pressed_keys: HashMap::new(),
-            mouse_delta: (0.0, 0.0),
-            elapsed_time: 0.0,
-            frame_time: 0.0,
-            fps_sample: 0.0,
-            fps_last_sampled_time: 0.0,
-            // camera_animation: Some(camera_animation),
-            camera_animation: None,
-            highlighted: None,
-            chunk: Chunk::new(Point3f::origin(), 51),
-        };
-        let renderer = Rc::new(RefCell::new(VulkanApp::new(screen_width, screen_height)?));
-
+        let mut state = GameState::new(player, input_map, region_store);
+        state.movement_speeds = movement_speeds;
+        state.screen_size = platform.window_size();
+        // state.camera_animation = Some(camera_animation);
+        let renderer: Rc<RefCell<dyn Renderer>> = Rc::new(RefCell::new(renderer));
+        let render_error = Rc::new(RefCell::new(None));
         let mut world = World::new();
         world.register::<TransformComponent>();
+        world.register::<PrevTransformComponent>();
         world.register::<PrimitiveGeometryComponent>();
         world.register::<AabbComponent>();
         world.register::<BlockComponent>();
 
-        let dispatcher = {
+        let update_dispatcher = {
             let mut transform_storage = world.write_storage::<TransformComponent>();
 
             DispatcherBuilder::new()
+                .with(TransformSnapshotSystem, "TransformSnapshotSystem", &[])
                 .with(
                     AabbComponentSystem::new(
                         transform_storage.register_reader(),
@@ -98,179 +337,525 @@ impl<'a, 'b> Game<'a, 'b> {
                     "AabbComponentSystem",
                     &[],
                 )
-                .with(SelectionSystem, "SelectionSystem", &["AabbComponentSystem"])
-                .with_thread_local(RenderSystem {
-                    renderer: renderer.clone(),
-                })
+                .with(
+                    OctreeSystem::new(transform_storage.register_reader()),
+                    "OctreeSystem",
+                    &["AabbComponentSystem"],
+                )
+                .with(MovementSystem::default(), "MovementSystem", &[])
+                .with(
+                    SelectionSystem,
+                    "SelectionSystem",
+                    &["OctreeSystem", "MovementSystem"],
+                )
+                .with(
+                    BlockInteractionSystem,
+                    "BlockInteractionSystem",
+                    &["SelectionSystem"],
+                )
                 .build()
         };
+        let render_dispatcher = DispatcherBuilder::new()
+            .with(CameraAnimationSystem, "CameraAnimationSystem", &[])
+            .with(
+                CameraControlSystem,
+                "CameraControlSystem",
+                &["CameraAnimationSystem"],
+            )
+            .with(
+                CameraInterpolationSystem,
+                "CameraInterpolationSystem",
+                &["CameraAnimationSystem"],
+            )
+            .with(FpsCounterSystem, "FpsCounterSystem", &[])
+            .with_thread_local(RenderSystem {
+                renderer,
+                error: render_error.clone(),
+            })
+            .build();
+
+        let streamed_around = player_chunk(&state);
+        stream_chunks(&mut state, &world_generator, world_radius, streamed_around)?;
 
-        // square
+        let (dx, dy, dz) = MARKER_OFFSET;
         world
             .create_entity()
-            .with(TransformComponent(
-                Translation3::from(Vector3f::new(0.0, -5.0, 0.0)).to_superset(),
-            ))
-            .with(PrimitiveGeometryComponent::Square(Square::new(100.0)))
-            .with(BlockComponent(BlockType::Cobblestone))
+            .with(TransformComponent(Transform3f::new_with_translation(
+                Vector3f::new(dx, world_generator.height(0, 0) as f32 + dy, dz),
+            )))
+            .with(PrimitiveGeometryComponent::UnitCube(UnitCube::new(1.0)))
+            .with(BlockComponent(BlockType::named("cobblestone")))
             .build();
 
-        state.chunk.insert(
-            entity::Entity::new_block_w(Transform3f::identity(), BlockType::Cobblestone, &world),
-            &world.read_storage(),
-        );
-        state.chunk.insert(
-            entity::Entity::new_block_w(
-                Translation3f::from(Vector3f::new(2.0, 0.0, -2.0)).to_superset(),
-                BlockType::Cobblestone,
-                &world,
-            ),
-            &world.read_storage(),
-        );
-        state.chunk.insert(
-            entity::Entity::new_block_w(
-                Translation3f::from(Vector3f::new(-2.0, 1.0, -2.0)).to_superset(),
-                BlockType::Cobblestone,
-                &world,
-            ),
-            &world.read_storage(),
-        );
-        state.chunk.insert(
-            entity::Entity::new_block_w(
-                Translation3f::from(Vector3f::new(1.0, 0.0, -2.0)).to_superset(),
-                BlockType::Sandstone,
-                &world,
-            ),
-            &world.read_storage(),
-        );
-        state.chunk.insert(
-            entity::Entity::new_block_w(
-                Translation3f::from(Vector3f::new(1.0, -1.0, -2.0)).to_superset(),
-                BlockType::Sandstone,
-                &world,
-            ),
-            &world.read_storage(),
-        );
-        state.chunk.insert(
-            entity::Entity::new_block_w(
-                Translation3f::from(Vector3f::new(1.0, 1.0, -2.0)).to_superset(),
-                BlockType::Cobblestone,
-                &world,
-            ),
-            &world.read_storage(),
-        );
-
         world.add_resource(state);
         Ok(Game {
             world,
-            dispatcher,
-            renderer,
+            update_dispatcher,
+            render_dispatcher,
+            platform,
+            focused: true,
+            should_grab_cursor: true,
+            cursor_grabbed: false,
+            already_changed_cursor_state: false,
+            accumulator: 0.0,
+            render_error,
+            world_generator,
+            world_radius,
+            streamed_around,
         })
     }
 
+    pub fn state(&self) -> Fetch<'_, GameState> {
+        self.world.read_resource::<GameState>()
+    }
+
+    pub fn state_mut(&mut self) -> FetchMut<'_, GameState> {
+        self.world.write_resource::<GameState>()
+    }
+
+    /// Runs frames until the player quits or a frame fails, then saves the world. A frame's error
+    /// takes precedence over one from saving.
     pub fn start(&mut self) -> Result<(), Error> {
-        let mut running = true;
-        let mut just_started = true;
-        let mut focused = true;
-        let mut should_grab_cursor = true;
-        let mut old_cursor_grabbed = should_grab_cursor;
-        let mut already_changed_cursor_state = false;
-        let start_time = Instant::now();
-        let mut last_frame_time = start_time;
-
-        self.toggle_cursor_grab(&self.renderer.borrow_mut(), true)?;
-
-        while running {
-            let mut resized = false;
+        let result = self.run_frames();
+        let saved = self.save();
+        result.and(saved)
+    }
+
+    fn run_frames(&mut self) -> Result<(), Error> {
+        let mut last_frame_time = Instant::now();
+        loop {
             let current_frame_time = Instant::now();
             let frame_time = current_frame_time.duration_since(last_frame_time);
             last_frame_time = current_frame_time;
-            let mut mouse_delta = (0.0, 0.0);
-            let mut new_cursor_grabbed = old_cursor_grabbed;
-
-            {
-                let mut state = self.world.write_resource::<GameState>();
-                let state = state.deref_mut();
-                let pressed_keys = &mut state.pressed_keys;
-                let mut renderer = self.renderer.borrow_mut();
-
-                renderer.events_loop().poll_events(|event| match event {
-                    Event::DeviceEvent { event, .. } => {
-                        if focused {
-                            on_device_event(&event, pressed_keys, &mut mouse_delta);
-                            if pressed_keys.contains_key(&VirtualKeyCode::Escape) {
-                                running = false;
-                            }
+            if !self.run_frame(frame_time.as_nanos() as f32 / NSEC_PER_SEC as f32)? {
+                return Ok(());
+            }
+        }
+    }
 
-                            if let Some(&count) = pressed_keys.get(&VirtualKeyCode::F1) {
-                                if count == 1 && !already_changed_cursor_state {
-                                    should_grab_cursor = !should_grab_cursor;
-                                    new_cursor_grabbed = should_grab_cursor;
-                                    already_changed_cursor_state = true;
-                                }
-                            } else {
-                                already_changed_cursor_state = false;
-                            }
+    /// Loads the chunks around the player and unloads the ones far from it, once it's entered a
+    /// new chunk.
+    fn stream_chunks(&mut self) -> Result<(), Error> {
+        let mut state = self.world.write_resource::<GameState>();
+        let center = player_chunk(&state);
+        if center == self.streamed_around {
+            return Ok(());
+        }
+        self.streamed_around = center;
+        stream_chunks(&mut state, &self.world_generator, self.world_radius, center)
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let state = self.state();
+        state.region_store.save_all(&state.chunk_map)?;
+        Ok(())
+    }
+
+    /// Handles events, runs as many ticks as fit in `frame_time` seconds and draws a frame.
+    /// Returns false once the game should stop.
+    pub fn run_frame(&mut self, frame_time: f32) -> Result<bool, Error> {
+        let mut running = true;
+        let mut resized = None;
+        let mut mouse_delta = (0.0, 0.0);
+        let mut wheel_delta = 0.0;
+        let mut new_cursor_grabbed = self.should_grab_cursor && self.focused;
+
+        {
+            let mut state = self.world.write_resource::<GameState>();
+            let state = state.deref_mut();
+            let input_map = &state.input_map;
+            let pressed_keys = &mut state.pressed_keys;
+            let pressed_buttons = &mut state.pressed_buttons;
+            // Cleared once a tick has handled them
+            let mouse_clicks = &mut state.mouse_clicks;
+            let focused = &mut self.focused;
+            let should_grab_cursor = &mut self.should_grab_cursor;
+            let already_changed_cursor_state = &mut self.already_changed_cursor_state;
+
+            self.platform.poll_events(&mut |event| match event {
+                PlatformEvent::CloseRequested => running = false,
+                PlatformEvent::Focused(f) => {
+                    *focused = f;
+                    if f {
+                        // only regrab cursor if should_grab_cursor is toggled
+                        if *should_grab_cursor {
+                            new_cursor_grabbed = true;
                         }
+                    } else {
+                        new_cursor_grabbed = false;
+                        // Release events won't arrive while unfocused
+                        pressed_keys.clear();
+                        pressed_buttons.clear();
                     }
-                    Event::WindowEvent { event, .. } => match event {
-                        WindowEvent::CloseRequested => running = false,
-                        WindowEvent::Focused(f) => {
-                            focused = f;
-                            if f {
-                                // only regrab cursor if should_grab_cursor is toggled
-                                if should_grab_cursor {
-                                    new_cursor_grabbed = true;
-                                }
-                            } else {
-                                new_cursor_grabbed = false;
-                            }
+                }
+                PlatformEvent::Resized { width, height } => {
+                    // FIXME: handle minimization?
+                    println!("resized to ({}, {})", width, height);
+                    resized = Some((width, height));
+                }
+                event => {
+                    if *focused {
+                        on_input_event(
+                            &event,
+                            pressed_keys,
+                            pressed_buttons,
+                            mouse_clicks,
+                            &mut mouse_delta,
+                            &mut wheel_delta,
+                        );
+                        if input_map.is_held(Action::Quit, pressed_keys, pressed_buttons) {
+                            running = false;
                         }
-                        WindowEvent::Resized(LogicalSize { width, height }) => {
-                            // FIXME: handle minimization?
-                            // Right now we don't allow the window to be resized
-                            if just_started {
-                                // When the window is first created, a resized event is sent
-                                just_started = false;
-                            } else {
-                                println!("resized to ({}, {})", width, height);
-                                resized = true;
+
+                        if let Some(count) = input_map.press_count(
+                            Action::ToggleCursor,
+                            pressed_keys,
+                            pressed_buttons,
+                        ) {
+                            if count == 1 && !*already_changed_cursor_state {
+                                *should_grab_cursor = !*should_grab_cursor;
+                                new_cursor_grabbed = *should_grab_cursor;
+                                *already_changed_cursor_state = true;
                             }
+                        } else {
+                            *already_changed_cursor_state = false;
                         }
-                        _ => (),
-                    },
-                    _ => (),
-                });
-
-                if new_cursor_grabbed != old_cursor_grabbed {
-                    self.toggle_cursor_grab(&renderer, new_cursor_grabbed)?;
-                    old_cursor_grabbed = new_cursor_grabbed;
-                }
-                if should_grab_cursor {
-                    state.mouse_delta = mouse_delta;
-                } else {
-                    state.mouse_delta = (0.0, 0.0);
+                    }
                 }
+            });
 
-                state.elapsed_time = start_time.elapsed().as_nanos() as f32 / NSEC_PER_SEC as f32;
-                state.frame_time = frame_time.as_nanos() as f32 / NSEC_PER_SEC as f32;
-                state.resized = resized;
+            if new_cursor_grabbed != self.cursor_grabbed {
+                self.platform.set_cursor_grab(new_cursor_grabbed)?;
+                self.cursor_grabbed = new_cursor_grabbed;
+            }
+            if self.should_grab_cursor {
+                state.mouse_delta = mouse_delta;
+                state.wheel_delta = wheel_delta;
+            } else {
+                state.mouse_delta = (0.0, 0.0);
+                state.wheel_delta = 0.0;
+                state.mouse_clicks.clear();
             }
 
-            self.dispatcher.dispatch(&self.world.res);
+            state.elapsed_time += frame_time;
+            state.frame_time = frame_time;
+            state.resized = resized;
+            if let Some(screen_size) = resized {
+                state.screen_size = screen_size;
+            }
+        }
+
+        self.accumulator += frame_time;
+        let mut ticks = 0;
+        while self.accumulator >= TICK_TIME {
+            if ticks == MAX_TICKS_PER_FRAME {
+                self.accumulator %= TICK_TIME;
+                break;
+            }
+            self.update_dispatcher.dispatch(&self.world.res);
             self.world.maintain();
+            self.state_mut().mouse_clicks.clear();
+            self.accumulator -= TICK_TIME;
+            ticks += 1;
         }
-        Ok(())
+        self.state_mut().tick_alpha = self.accumulator / TICK_TIME;
+
+        self.stream_chunks()?;
+
+        self.render_dispatcher.dispatch(&self.world.res);
+        self.world.maintain();
+        if let Some(err) = self.render_error.borrow_mut().take() {
+            return Err(err.into());
+        }
+        Ok(running)
     }
+}
 
-    fn toggle_cursor_grab(
-        &self,
-        renderer: &RefMut<dyn Renderer + 'static>,
-        active: bool,
-    ) -> Result<(), Error> {
-        let window = renderer.window();
-        window.grab_cursor(active).map_err(err_msg)?;
-        window.hide_cursor(active);
-        Ok(())
+/// Chunk that contains the player's feet
+fn player_chunk(state: &GameState) -> ChunkCoords {
+    let pos = state.player.pos;
+    let block = Point3f::new(pos.x.round(), pos.y.round(), pos.z.round());
+    state.chunk_map.chunk_coords(&block)
+}
+
+/// Unloads and saves the chunks beyond `radius` of `center`, plus `UNLOAD_MARGIN`, and loads the
+/// ones within it, generating those that were never saved.
+fn stream_chunks(
+    state: &mut GameState,
+    world_generator: &WorldGenerator,
+    radius: i32,
+    center: ChunkCoords,
+) -> Result<(), Error> {
+    let (cx, cy, cz) = center;
+    let far: Vec<ChunkCoords> = state
+        .chunk_map
+        .chunks()
+        .map(|(&coords, _)| coords)
+        .filter(|&(x, y, z)| {
+            (x - cx).abs() > radius + UNLOAD_MARGIN
+                || (y - cy).abs() > VERTICAL_RADIUS + UNLOAD_MARGIN
+                || (z - cz).abs() > radius + UNLOAD_MARGIN
+        })
+        .collect();
+    for coords in far {
+        state.region_store.unload(&mut state.chunk_map, coords)?;
+    }
+
+    for x in cx - radius..=cx + radius {
+        for y in cy - VERTICAL_RADIUS..=cy + VERTICAL_RADIUS {
+            for z in cz - radius..=cz + radius {
+                let coords = (x, y, z);
+                if state.chunk_map.chunk(coords).is_some() {
+                    continue;
+                }
+                if !state.region_store.load(&mut state.chunk_map, coords)? {
+                    world_generator.generate(&mut state.chunk_map, coords);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        platform::ScriptedPlatform,
+        renderer::{HeadlessRenderer, RenderData, RendererResult},
+        vulkan::error::VulkanError,
+    };
+
+    fn key(keycode: VirtualKeyCode, pressed: bool) -> PlatformEvent {
+        PlatformEvent::Key { keycode, pressed }
+    }
+
+    #[test]
+    fn test_headless() {
+        let dir = std::env::temp_dir().join(format!("minecrust-game-{}", std::process::id()));
+        let renderer = HeadlessRenderer::new();
+        let frames = renderer.frames();
+        let platform = ScriptedPlatform::new(vec![
+            vec![key(VirtualKeyCode::W, true)],
+            vec![PlatformEvent::Resized {
+                width: 640,
+                height: 480,
+            }],
+            vec![key(VirtualKeyCode::W, false)],
+            vec![PlatformEvent::CloseRequested],
+        ]);
+        let mut game = GameBuilder::new()
+            .platform(Box::new(platform))
+            .renderer(Box::new(renderer))
+            .input_map(InputMap::default())
+            .save_dir(&dir)
+            .world_radius(0)
+            .build()
+            .unwrap();
+        let start = game.state().player.pos;
+        assert_eq!(game.state().chunk_map.len(), 3);
+
+        assert!(game.run_frame(0.25).unwrap());
+        assert!(game.run_frame(0.25).unwrap());
+        assert!(game.run_frame(0.25).unwrap());
+        let stopped = game.state().player.pos;
+        assert!(!game.run_frame(0.25).unwrap());
+
+        let frames = frames.borrow();
+        assert_eq!(frames.len(), 4);
+        // Only the chunk the camera stands in is in view, so the ones above and below it aren't
+        // meshed
+        assert_eq!(frames[0].visible_chunk_count, 1);
+        assert_eq!(frames[0].chunk_mesh_count, 1);
+        assert_eq!(frames[1].chunk_mesh_count, 0);
+        assert_eq!(frames[0].resized, None);
+        assert_eq!(frames[1].resized, Some((640, 480)));
+        // The camera starts out looking towards -z, so W walks that way until it's released
+        assert!(stopped.z < start.z - 1.0);
+        let state = game.state();
+        assert!(state.player.pos.z.almost_eq(stopped.z));
+        assert_eq!(frames[3].camera_pos, state.player.eye());
+        assert!(state.elapsed_time.almost_eq(1.0));
+
+        game.save().unwrap();
+        assert!(dir.is_dir());
+        drop(state);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unfocus_releases_keys() {
+        let dir = std::env::temp_dir().join(format!("minecrust-focus-{}", std::process::id()));
+        let platform = ScriptedPlatform::new(vec![
+            vec![key(VirtualKeyCode::W, true)],
+            // W is released while the window is unfocused, so the release never arrives
+            vec![PlatformEvent::Focused(false)],
+            vec![PlatformEvent::Focused(true)],
+        ]);
+        let mut game = GameBuilder::new()
+            .platform(Box::new(platform))
+            .renderer(Box::new(HeadlessRenderer::new()))
+            .input_map(InputMap::default())
+            .save_dir(&dir)
+            .world_radius(0)
+            .build()
+            .unwrap();
+
+        assert!(game.run_frame(0.25).unwrap());
+        assert!(!game.state().pressed_keys.is_empty());
+        assert!(game.run_frame(0.25).unwrap());
+        assert!(game.state().pressed_keys.is_empty());
+        let stopped = game.state().player.pos;
+        assert!(game.run_frame(0.25).unwrap());
+        assert!(game.state().player.pos.z.almost_eq(stopped.z));
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_break_entity() {
+        let dir = std::env::temp_dir().join(format!("minecrust-entity-{}", std::process::id()));
+        let click = |pressed| PlatformEvent::MouseButton {
+            button: MouseButton::Left,
+            pressed,
+        };
+        let platform = ScriptedPlatform::new(vec![vec![], vec![click(true), click(false)], vec![]]);
+        let mut game = GameBuilder::new()
+            .platform(Box::new(platform))
+            .renderer(Box::new(HeadlessRenderer::new()))
+            .input_map(InputMap::default())
+            .save_dir(&dir)
+            .world_radius(0)
+            .build()
+            .unwrap();
+        let marker = {
+            let entities = game.world.entities();
+            let blocks = game.world.read_storage::<BlockComponent>();
+            let marker = (&entities, &blocks).join().next().unwrap().0;
+            Entity::from(marker)
+        };
+        let center = *marker.transform(&game.world.read_storage()) * Point3f::origin();
+        {
+            let mut state = game.state_mut();
+            let eye = state.player.eye();
+            state.camera.pos = eye;
+            state.camera.rotate_to_dir(&(center - eye));
+        }
+
+        // The marker is selected once it's in the octree
+        assert!(game.run_frame(TICK_TIME).unwrap());
+        assert_eq!(game.state().entity_octree.len(), 1);
+        assert_eq!(game.state().highlighted_entity, Some(marker));
+
+        // Clicking it deletes it, and the next tick takes it out of the octree
+        assert!(game.run_frame(TICK_TIME).unwrap());
+        assert!(!game.world.is_alive(marker.entity));
+        assert!(game.run_frame(TICK_TIME).unwrap());
+        assert!(game.state().entity_octree.is_empty());
+        assert_eq!(game.state().highlighted_entity, None);
+    }
+
+    /// Fails every frame
+    struct FailingRenderer;
+
+    impl Renderer for FailingRenderer {
+        fn draw_frame(
+            &mut self,
+            _state: &GameState,
+            _render_data: &RenderData,
+            _resized: Option<(u32, u32)>,
+        ) -> RendererResult<()> {
+            Err(VulkanError::Str("device lost".to_owned()).into())
+        }
+    }
+
+    #[test]
+    fn test_save_on_error() {
+        let dir = std::env::temp_dir().join(format!("minecrust-error-{}", std::process::id()));
+        let mut game = GameBuilder::new()
+            .platform(Box::new(ScriptedPlatform::default()))
+            .renderer(Box::new(FailingRenderer))
+            .input_map(InputMap::default())
+            .save_dir(&dir)
+            .world_radius(0)
+            .build()
+            .unwrap();
+        let pos = Point3f::new(0.0, 1.0, 0.0);
+        game.state_mut()
+            .chunk_map
+            .insert(&pos, BlockType::named("cobblestone"));
+
+        assert!(game.start().is_err());
+        let state = game.state();
+        let mut chunk_map = ChunkMap::new(CHUNK_SIDE_LEN);
+        let coords = chunk_map.chunk_coords(&pos);
+        assert!(state.region_store.load(&mut chunk_map, coords).unwrap());
+        assert_eq!(chunk_map.get(&pos), Some(BlockType::named("cobblestone")));
+        drop(state);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stream_chunks() {
+        let dir = std::env::temp_dir().join(format!("minecrust-stream-{}", std::process::id()));
+        let mut game = GameBuilder::new()
+            .platform(Box::new(ScriptedPlatform::default()))
+            .renderer(Box::new(HeadlessRenderer::new()))
+            .input_map(InputMap::default())
+            .save_dir(&dir)
+            .world_radius(0)
+            .build()
+            .unwrap();
+        let pos = Point3f::new(0.0, 1.0, 0.0);
+        let start = game.state().player.pos;
+        let coords = {
+            let mut state = game.state_mut();
+            state
+                .chunk_map
+                .insert(&pos, BlockType::named("cobblestone"));
+            state.chunk_map.chunk_coords(&pos)
+        };
+
+        // Walking one chunk away keeps the old chunk around
+        let side_len = CHUNK_SIDE_LEN as f32;
+        game.state_mut().player.pos = start + Vector3f::new(side_len, 0.0, 0.0);
+        game.run_frame(0.0).unwrap();
+        assert!(game.state().chunk_map.chunk(coords).is_some());
+        assert!(game.state().chunk_map.chunk((1, coords.1, 0)).is_some());
+
+        // Going further unloads it, saving it to disk
+        game.state_mut().player.pos = start + Vector3f::new(3.0 * side_len, 0.0, 0.0);
+        game.run_frame(0.0).unwrap();
+        {
+            let state = game.state();
+            assert!(state.chunk_map.chunk(coords).is_none());
+            assert!(state.chunk_map.chunk((3, coords.1, 0)).is_some());
+            let mut chunk_map = ChunkMap::new(CHUNK_SIDE_LEN);
+            assert!(state.region_store.load(&mut chunk_map, coords).unwrap());
+            assert_eq!(chunk_map.get(&pos), Some(BlockType::named("cobblestone")));
+        }
+
+        // Coming back reads it back instead of generating it again
+        game.state_mut().player.pos = start;
+        game.run_frame(0.0).unwrap();
+        let state = game.state();
+        assert!(state.chunk_map.chunk((3, coords.1, 0)).is_none());
+        assert_eq!(
+            state.chunk_map.get(&pos),
+            Some(BlockType::named("cobblestone"))
+        );
+        drop(state);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_no_window_needs_renderer() {
+        let result = GameBuilder::new()
+            .platform(Box::new(ScriptedPlatform::default()))
+            .input_map(InputMap::default())
+            .world_radius(0)
+            .build();
+        assert!(result.is_err());
     }
 }
@@ -1,5 +1,6 @@
 mod aabb;
 mod aap;
+mod frustum;
 mod ray;
 mod rectangle;
 mod square;
@@ -8,10 +9,11 @@ mod unitcube;
 pub use self::{
     aabb::Aabb,
     aap::{Axis, AAP},
-    ray::Ray,
+    frustum::{Frustum, Plane},
+    ray::{Ray, VoxelHit},
     rectangle::Rectangle,
     square::Square,
-    unitcube::UnitCube,
+    unitcube::{face_uv, UnitCube},
 };
 
 use crate::{types::prelude::*, vulkan::Vertex3f};
@@ -143,6 +143,32 @@ impl Aabb {
         let v = point - self.center;
         v.x.abs() <= self.extents.x && v.y.abs() <= self.extents.y && v.z.abs() <= self.extents.z
     }
+
+    /// Distance from `point` to the closest point of the box, or 0 if it's inside.
+    pub fn distance(&self, point: &Point3f) -> f32 {
+        let v = point - self.center;
+        Vector3f::new(
+            (v.x.abs() - self.extents.x).max(0.0),
+            (v.y.abs() - self.extents.y).max(0.0),
+            (v.z.abs() - self.extents.z).max(0.0),
+        )
+        .norm()
+    }
+
+    /// Returns true if `other` lies entirely inside `self`, including on its boundary.
+    pub fn contains_aabb(&self, other: &Aabb) -> bool {
+        let (min, max) = (self.min(), self.max());
+        let (other_min, other_max) = (other.min(), other.max());
+        (0..3).all(|i| min[i] <= other_min[i] && other_max[i] <= max[i])
+    }
+
+    /// Returns true if `self` and `other` overlap. Boxes that only touch don't overlap.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        let d = other.center - self.center;
+        let e = self.extents + other.extents;
+        let overlaps = |d: f32, e: f32| d.abs() < e && !d.abs().almost_eq(e);
+        overlaps(d.x, e.x) && overlaps(d.y, e.y) && overlaps(d.z, e.z)
+    }
 }
 
 #[cfg(test)]
@@ -160,6 +186,53 @@ mod tests {
         assert_eq!(aabb.face(&Point3f::new(-0.5, 0.5, -1.0)), Some(Face::Back));
     }
 
+    #[test]
+    fn test_intersects() {
+        let aabb = Aabb::new(Point3f::origin(), Vector3f::repeat(0.5));
+        assert!(aabb.intersects(&aabb));
+        assert!(aabb.intersects(&Aabb::new(
+            Point3f::new(0.9, 0.0, 0.0),
+            Vector3f::repeat(0.5)
+        )));
+        // Touching faces
+        assert!(!aabb.intersects(&Aabb::new(
+            Point3f::new(1.0, 0.0, 0.0),
+            Vector3f::repeat(0.5)
+        )));
+        assert!(!aabb.intersects(&Aabb::new(
+            Point3f::new(0.0, 0.0, -3.0),
+            Vector3f::repeat(1.0)
+        )));
+        // Tall box overlapping on every axis
+        assert!(aabb.intersects(&Aabb::new(
+            Point3f::new(0.3, 1.2, 0.3),
+            Vector3f::new(0.3, 0.9, 0.3)
+        )));
+    }
+
+    #[test]
+    fn test_distance() {
+        let aabb = Aabb::new(Point3f::origin(), Vector3f::new(1.0, 2.0, 3.0));
+        assert_eq!(aabb.distance(&Point3f::new(0.5, -1.0, 2.0)), 0.0);
+        assert_eq!(aabb.distance(&Point3f::new(3.0, 0.0, 0.0)), 2.0);
+        assert_eq!(aabb.distance(&Point3f::new(-4.0, 6.0, 1.0)), 5.0);
+    }
+
+    #[test]
+    fn test_contains_aabb() {
+        let aabb = Aabb::new_min_max(Point3f::new(-1.0, -1.0, -1.0), Point3f::new(1.0, 1.0, 1.0));
+        assert!(aabb.contains_aabb(&aabb));
+        assert!(aabb.contains_aabb(&Aabb::new(
+            Point3f::new(0.5, 0.5, -0.5),
+            Vector3f::repeat(0.5)
+        )));
+        assert!(!aabb.contains_aabb(&Aabb::new(
+            Point3f::new(0.6, 0.5, -0.5),
+            Vector3f::repeat(0.5)
+        )));
+        assert!(!aabb.contains_aabb(&Aabb::new(Point3f::origin(), Vector3f::repeat(2.0))));
+    }
+
     #[test]
     fn test_partition() {
         let aabb = Aabb::new_min_max(Point3f::new(-1.0, -1.0, -1.0), Point3f::new(1.0, 1.0, 1.0));
@@ -0,0 +1,166 @@
+use crate::{geometry::Aabb, types::prelude::*};
+
+/// The plane `normal.dot(p) + d = 0`. `normal` is a unit vector pointing to the plane's front.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Plane {
+    pub normal: Vector3f,
+    pub d: f32,
+}
+
+impl Plane {
+    /// The plane `a * x + b * y + c * z + d = 0`.
+    pub fn from_coefficients(v: Vector4f) -> Plane {
+        let len = Vector3f::new(v.x, v.y, v.z).norm();
+        Plane {
+            normal: Vector3f::new(v.x, v.y, v.z) / len,
+            d: v.w / len,
+        }
+    }
+
+    /// Signed distance from the plane to `point`. Positive in front of the plane.
+    pub fn distance(&self, point: &Point3f) -> f32 {
+        self.normal.dot(&point.coords) + self.d
+    }
+
+    /// Half the extent of `aabb` along the plane's normal.
+    fn radius(&self, aabb: &Aabb) -> f32 {
+        aabb.extents.dot(&self.normal.abs())
+    }
+}
+
+/// A view frustum: the space in front of all of its planes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far
+    planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the frustum of projection-view matrix `proj_view`, which maps the points inside it
+    /// to Vulkan's clip volume: -w <= x <= w, -w <= y <= w and 0 <= z <= w.
+    pub fn from_matrix(proj_view: &Matrix4f) -> Frustum {
+        let row = |i: usize| {
+            Vector4f::new(
+                proj_view[(i, 0)],
+                proj_view[(i, 1)],
+                proj_view[(i, 2)],
+                proj_view[(i, 3)],
+            )
+        };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Frustum {
+            planes: [
+                Plane::from_coefficients(w + x),
+                Plane::from_coefficients(w - x),
+                Plane::from_coefficients(w + y),
+                Plane::from_coefficients(w - y),
+                Plane::from_coefficients(z),
+                Plane::from_coefficients(w - z),
+            ],
+        }
+    }
+
+    pub fn planes(&self) -> &[Plane; 6] {
+        &self.planes
+    }
+
+    pub fn contains_point(&self, point: &Point3f) -> bool {
+        self.planes.iter().all(|plane| plane.distance(point) >= 0.0)
+    }
+
+    /// Returns true if `aabb` lies entirely inside the frustum.
+    pub fn contains_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.distance(&aabb.center) >= plane.radius(aabb))
+    }
+
+    /// Returns true unless `aabb` lies entirely behind one of the planes. Boxes just outside a
+    /// corner of the frustum may also count as intersecting.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.distance(&aabb.center) >= -plane.radius(aabb))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{Camera, FOV_Y, Z_FAR, Z_NEAR};
+
+    /// Looks down -z from the origin with a 45 degree field of view. At z = -5 it reaches about
+    /// 2.07 to each side.
+    fn new_frustum() -> Frustum {
+        Camera::new(Point3f::origin(), -Vector3f::z_axis()).frustum(1.0)
+    }
+
+    #[test]
+    fn test_from_matrix() {
+        let frustum = Frustum::from_matrix(&Matrix4f::identity());
+        let expected = [
+            (Vector3f::x(), 1.0),
+            (-Vector3f::x(), 1.0),
+            (Vector3f::y(), 1.0),
+            (-Vector3f::y(), 1.0),
+            (Vector3f::z(), 0.0),
+            (-Vector3f::z(), 1.0),
+        ];
+        for (plane, (normal, d)) in frustum.planes().iter().zip(expected.iter()) {
+            assert!(plane.normal.almost_eq(normal));
+            assert!(plane.d.almost_eq(*d));
+        }
+
+        let frustum = new_frustum();
+        let planes = frustum.planes();
+        // The near and far planes face each other along the view direction
+        assert!(planes[4].normal.almost_eq(&-Vector3f::z()));
+        assert!((planes[4].d + Z_NEAR).abs() < 1e-4);
+        assert!(planes[5].normal.almost_eq(&Vector3f::z()));
+        assert!((planes[5].d - Z_FAR).abs() < 1e-2);
+        // The side planes are half the field of view off the view direction
+        let half_fov = (FOV_Y / 2.0).to_radians();
+        for plane in &planes[..4] {
+            assert!(plane.normal.dot(&-Vector3f::z()).almost_eq(half_fov.sin()));
+            assert!(plane.d.almost_eq(0.0));
+        }
+    }
+
+    #[test]
+    fn test_contains_point() {
+        let frustum = new_frustum();
+        assert!(frustum.contains_point(&Point3f::new(0.0, 0.0, -1.0)));
+        assert!(frustum.contains_point(&Point3f::new(2.0, -2.0, -5.0)));
+        assert!(!frustum.contains_point(&Point3f::new(2.2, 0.0, -5.0)));
+        assert!(!frustum.contains_point(&Point3f::new(0.0, 0.0, 1.0)));
+        assert!(!frustum.contains_point(&Point3f::new(0.0, 0.0, -0.05)));
+        assert!(!frustum.contains_point(&Point3f::new(0.0, 0.0, -101.0)));
+    }
+
+    #[test]
+    fn test_intersects_aabb() {
+        let frustum = new_frustum();
+        let unit = Vector3f::repeat(0.5);
+        assert!(frustum.intersects_aabb(&Aabb::new(Point3f::new(0.0, 0.0, -5.0), unit)));
+        // Containing the camera
+        assert!(frustum.intersects_aabb(&Aabb::new(Point3f::origin(), unit)));
+        // Straddling the right plane
+        assert!(frustum.intersects_aabb(&Aabb::new(Point3f::new(2.3, 0.0, -5.0), unit)));
+        assert!(!frustum.intersects_aabb(&Aabb::new(Point3f::new(3.5, 0.0, -5.0), unit)));
+        assert!(!frustum.intersects_aabb(&Aabb::new(Point3f::new(0.0, 0.0, 2.0), unit)));
+        assert!(!frustum.intersects_aabb(&Aabb::new(Point3f::new(0.0, -10.0, -5.0), unit)));
+    }
+
+    #[test]
+    fn test_contains_aabb() {
+        let frustum = new_frustum();
+        let unit = Vector3f::repeat(0.5);
+        assert!(frustum.contains_aabb(&Aabb::new(Point3f::new(0.0, 0.0, -5.0), unit)));
+        assert!(frustum.contains_aabb(&Aabb::new(Point3f::new(1.0, 1.0, -5.0), unit)));
+        // Intersecting, but not contained
+        assert!(!frustum.contains_aabb(&Aabb::new(Point3f::new(2.3, 0.0, -5.0), unit)));
+        assert!(!frustum.contains_aabb(&Aabb::new(Point3f::origin(), unit)));
+        assert!(!frustum.contains_aabb(&Aabb::new(Point3f::new(0.0, 0.0, -100.0), unit)));
+        assert!(!frustum.contains_aabb(&Aabb::new(Point3f::new(0.0, 0.0, 2.0), unit)));
+    }
+}
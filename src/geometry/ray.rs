@@ -6,6 +6,17 @@ use crate::{
 use specs::ReadStorage;
 use std::{f32, mem};
 
+/// A cell found by `Ray::cast_voxels`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VoxelHit {
+    /// Center of the cell
+    pub pos: Point3f,
+    /// Face of the cell that the ray entered through
+    pub face: Face,
+    /// Distance along the ray to where it entered the cell
+    pub t: f32,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Ray {
     pub origin: Point3f,
@@ -67,6 +78,70 @@ impl Ray {
         }
     }
 
+    /// Walks the unit cells centered on integer coordinates that the ray passes through, in order,
+    /// and returns the first one for which `is_solid` returns true. Uses Amanatides and Woo's grid
+    /// traversal. Cells entered farther than `reach` along the ray aren't visited, so `reach` must
+    /// be finite.
+    ///
+    /// If the ray starts inside a solid cell, that cell is returned at distance 0, entered through
+    /// the face that points most directly back along the ray.
+    pub fn cast_voxels<F>(&self, reach: f32, mut is_solid: F) -> Option<VoxelHit>
+    where
+        F: FnMut(&Point3f) -> bool,
+    {
+        let direction = self.direction.as_ref();
+        let mut cell = [0; 3];
+        let mut step = [0; 3];
+        // Distance along the ray to the next cell boundary on each axis
+        let mut t_max = [f32::INFINITY; 3];
+        // Distance along the ray between cell boundaries on each axis
+        let mut t_delta = [f32::INFINITY; 3];
+        for axis in 0..3 {
+            let origin = self.origin[axis];
+            let d = direction[axis];
+            cell[axis] = (origin + 0.5).floor() as i32;
+            if d > 0.0 {
+                step[axis] = 1;
+                t_max[axis] = (cell[axis] as f32 + 0.5 - origin) / d;
+                t_delta[axis] = 1.0 / d;
+            } else if d < 0.0 {
+                step[axis] = -1;
+                t_max[axis] = (cell[axis] as f32 - 0.5 - origin) / d;
+                t_delta[axis] = -1.0 / d;
+            }
+        }
+
+        let mut t = 0.0;
+        let mut axis = direction.iamax();
+        loop {
+            let pos = Point3f::new(cell[0] as f32, cell[1] as f32, cell[2] as f32);
+            if is_solid(&pos) {
+                return Some(VoxelHit {
+                    pos,
+                    face: entry_face(axis, step[axis]),
+                    t,
+                });
+            }
+            axis = if t_max[0] < t_max[1] {
+                if t_max[0] < t_max[2] {
+                    0
+                } else {
+                    2
+                }
+            } else if t_max[1] < t_max[2] {
+                1
+            } else {
+                2
+            };
+            t = t_max[axis];
+            if t > reach {
+                return None;
+            }
+            cell[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+        }
+    }
+
     fn intersect_aabb_optional(&self, aabb: &Option<Aabb>) -> Option<(f32, Point3f)> {
         if let Some(aabb) = aabb {
             self.intersect_aabb(aabb)
@@ -84,7 +159,10 @@ impl Ray {
         Some(aabb)
     }
 
-    fn intersect_aabbs_optional(&self, aabbs: &[Option<Aabb>]) -> Option<(usize, (f32, Point3f))> {
+    pub fn intersect_aabbs_optional(
+        &self,
+        aabbs: &[Option<Aabb>],
+    ) -> Option<(usize, (f32, Point3f))> {
         let aabb = aabbs
             .iter()
             .enumerate()
@@ -138,6 +216,18 @@ impl Ray {
     }
 }
 
+/// The face of a cell that a ray moving by `step` along `axis` enters through.
+fn entry_face(axis: usize, step: i32) -> Face {
+    match (axis, step > 0) {
+        (0, true) => Face::Left,
+        (0, false) => Face::Right,
+        (1, true) => Face::Bottom,
+        (1, false) => Face::Top,
+        (_, true) => Face::Back,
+        (_, false) => Face::Front,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -270,4 +360,61 @@ mod tests {
             entities[1]
         );
     }
+
+    #[test]
+    fn test_cast_voxels() {
+        let solid = [Point3f::new(3.0, 0.0, 0.0), Point3f::new(0.0, -2.0, 1.0)];
+        let is_solid = |pos: &Point3f| solid.contains(pos);
+
+        let r = Ray::new(Point3f::new(0.2, 0.1, 0.0), Vector3f::x());
+        let hit = r.cast_voxels(10.0, is_solid).unwrap();
+        assert_eq!(hit.pos, Point3f::new(3.0, 0.0, 0.0));
+        assert_eq!(hit.face, Face::Left);
+        assert!(hit.t.almost_eq(2.3));
+        // Out of reach
+        assert_eq!(r.cast_voxels(2.0, is_solid), None);
+        // Pointing away
+        let r = Ray::new(Point3f::new(0.2, 0.1, 0.0), -Vector3f::x());
+        assert_eq!(r.cast_voxels(10.0, is_solid), None);
+
+        // Diagonal ray entering through the top
+        let r = Ray::new(Point3f::new(0.0, 0.0, 0.0), Vector3f::new(0.0, -2.0, 1.0));
+        let hit = r.cast_voxels(10.0, is_solid).unwrap();
+        assert_eq!(hit.pos, Point3f::new(0.0, -2.0, 1.0));
+        assert_eq!(hit.face, Face::Top);
+        assert!(r.at(hit.t).almost_eq(&Point3f::new(0.0, -1.5, 0.75)));
+
+        // Starting inside a solid cell
+        let r = Ray::new(Point3f::new(3.1, 0.0, 0.0), Vector3f::new(-1.0, 0.2, 0.0));
+        let hit = r.cast_voxels(10.0, is_solid).unwrap();
+        assert_eq!(hit.pos, Point3f::new(3.0, 0.0, 0.0));
+        assert_eq!(hit.face, Face::Right);
+        assert_eq!(hit.t, 0.0);
+    }
+
+    #[test]
+    fn test_cast_voxels_order() {
+        // Every visited cell is adjacent to the previous one and farther along the ray
+        let r = Ray::new(Point3f::new(0.3, -0.2, 0.4), Vector3f::new(1.0, 0.7, -0.4));
+        let mut cells = vec![];
+        r.cast_voxels(20.0, |pos| {
+            cells.push(*pos);
+            false
+        });
+        assert_eq!(cells[0], Point3f::origin());
+        for pair in cells.windows(2) {
+            assert!(f32::almost_eq((pair[1] - pair[0]).norm(), 1.0));
+            let along = |p: &Point3f| (p - r.origin).dot(r.direction.as_ref());
+            assert!(along(&pair[1]) > along(&pair[0]) - 1.0);
+        }
+        // Agrees with testing each cell's AABB
+        for pos in &cells {
+            let aabb = Aabb::new(*pos, Vector3f::repeat(0.5));
+            let hit = r.cast_voxels(20.0, |p| p == pos).unwrap();
+            match r.intersect_aabb(&aabb) {
+                Some((t, _)) if *pos != Point3f::origin() => assert!((t - hit.t).abs() < 1e-4),
+                _ => assert_eq!(hit.t, 0.0),
+            }
+        }
+    }
 }
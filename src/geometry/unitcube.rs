@@ -54,14 +54,44 @@ impl UnitCube {
             transforms,
         }
     }
+
+    /// Transform that takes a `Square` in the xz plane to `face` of the cube.
+    pub fn face_transform(&self, face: Face) -> &Transform3f {
+        &self.transforms[face as usize]
+    }
+
+    /// Vertices of a single face of the cube. The face's texture covers it exactly once.
+    pub fn face_vtx_data(&self, face: Face, transform: &Transform3f) -> Vec<Vertex3f> {
+        Square::new(self.side_len)
+            .vtx_data(self.face_transform(face))
+            .into_iter()
+            .map(|vtx| {
+                let uv = face_uv(
+                    face,
+                    &(vtx.pos.coords / self.side_len),
+                    &Vector3f::repeat(1.0),
+                );
+                Vertex3f::new(transform * vtx.pos, uv)
+            })
+            .collect()
+    }
+}
+
+/// Texture coordinates of `offset`, a point on `face` relative to the face's center, for a face
+/// whose extent along each axis is `size`. Textures repeat once per unit.
+pub fn face_uv(face: Face, offset: &Vector3f, size: &Vector3f) -> Point2f {
+    let (u, v) = face.uv_axes();
+    Point2f::new(
+        offset.dot(&u) + size.dot(&u).abs() / 2.0,
+        offset.dot(&v) + size.dot(&v).abs() / 2.0,
+    )
 }
 
 impl PrimitiveGeometry for UnitCube {
     fn vtx_data(&self, transform: &Transform3f) -> Vec<Vertex3f> {
-        let sq = Square::new(self.side_len);
         let mut vertices = vec![];
-        for tr in &self.transforms {
-            vertices.extend(sq.vtx_data(&(transform * tr)));
+        for &face in &Face::ALL {
+            vertices.extend(self.face_vtx_data(face, transform));
         }
         vertices
     }
@@ -86,3 +116,35 @@ impl PrimitiveGeometry for UnitCube {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uv_axes() {
+        for &face in &Face::ALL {
+            let (u, v) = face.uv_axes();
+            // Right x up points out of the face
+            assert_eq!(u.cross(&-v), face.normal());
+        }
+    }
+
+    #[test]
+    fn test_face_vtx_data() {
+        let cube = UnitCube::new(2.0);
+        let center = Point3f::new(1.0, 2.0, 3.0);
+        let transform = Transform3f::new_with_translation(center.coords);
+        for &face in &Face::ALL {
+            let (u, v) = face.uv_axes();
+            let vertices = cube.face_vtx_data(face, &transform);
+            assert_eq!(vertices.len(), 6);
+            for vtx in vertices {
+                let offset = vtx.pos - center;
+                assert!(offset.dot(&face.normal()).almost_eq(1.0));
+                assert!(vtx.uv.x.almost_eq((offset.dot(&u) + 1.0) / 2.0));
+                assert!(vtx.uv.y.almost_eq((offset.dot(&v) + 1.0) / 2.0));
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use failure_derive::Fail;
+use serde::Deserialize;
+use std::{collections::HashMap, fs, path::Path};
+use winit::{MouseButton, VirtualKeyCode};
+
+/// Path of the bindings loaded at startup
+pub const INPUT_MAP_PATH: &str = "assets/input.ron";
+
+#[derive(Fail, Debug)]
+pub enum InputMapError {
+    #[fail(display = "{}", _0)]
+    Io(#[cause] std::io::Error),
+    #[fail(display = "{}", _0)]
+    Parse(#[cause] ron::de::Error),
+}
+
+impl From<std::io::Error> for InputMapError {
+    fn from(err: std::io::Error) -> InputMapError {
+        InputMapError::Io(err)
+    }
+}
+
+impl From<ron::de::Error> for InputMapError {
+    fn from(err: ron::de::Error) -> InputMapError {
+        InputMapError::Parse(err)
+    }
+}
+
+/// Something the player can do, independent of the key or button that does it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    /// Also flies up
+    Jump,
+    /// Also flies down
+    Crouch,
+    Sprint,
+    CycleMovementMode,
+    BreakBlock,
+    PlaceBlock,
+    ToggleCursor,
+    Quit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+/// Maps actions to the keys and mouse buttons that trigger them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputMap {
+    bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl Default for InputMap {
+    fn default() -> InputMap {
+        use self::{Action::*, Binding::*};
+        let bindings = vec![
+            (MoveForward, vec![Key(VirtualKeyCode::W)]),
+            (MoveBackward, vec![Key(VirtualKeyCode::S)]),
+            (MoveLeft, vec![Key(VirtualKeyCode::A)]),
+            (MoveRight, vec![Key(VirtualKeyCode::D)]),
+            (Jump, vec![Key(VirtualKeyCode::Space)]),
+            (Crouch, vec![Key(VirtualKeyCode::LShift)]),
+            (Sprint, vec![Key(VirtualKeyCode::LControl)]),
+            (CycleMovementMode, vec![Key(VirtualKeyCode::F2)]),
+            (BreakBlock, vec![Mouse(MouseButton::Left)]),
+            (PlaceBlock, vec![Mouse(MouseButton::Right)]),
+            (ToggleCursor, vec![Key(VirtualKeyCode::F1)]),
+            (Quit, vec![Key(VirtualKeyCode::Escape)]),
+        ];
+        InputMap {
+            bindings: bindings.into_iter().collect(),
+        }
+    }
+}
+
+impl InputMap {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<InputMap, InputMapError> {
+        InputMap::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses a map from actions to lists of bindings. Actions that aren't in `s` keep their
+    /// default bindings.
+    pub fn parse(s: &str) -> Result<InputMap, InputMapError> {
+        let bindings: HashMap<Action, Vec<Binding>> = ron::de::from_str(s)?;
+        let mut map = InputMap::default();
+        map.bindings.extend(bindings);
+        Ok(map)
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings
+            .get(&action)
+            .map(|bindings| bindings.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Replaces the bindings of `action`.
+    pub fn bind(&mut self, action: Action, bindings: Vec<Binding>) {
+        self.bindings.insert(action, bindings);
+    }
+
+    /// Actions triggered by `binding`.
+    pub fn actions(&self, binding: Binding) -> impl Iterator<Item = Action> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |(_, bindings)| bindings.contains(&binding))
+            .map(|(&action, _)| action)
+    }
+
+    /// Returns the largest press count of the held bindings of `action`, or `None` if none of
+    /// them are held.
+    pub fn press_count(
+        &self,
+        action: Action,
+        pressed_keys: &HashMap<VirtualKeyCode, usize>,
+        pressed_buttons: &HashMap<MouseButton, usize>,
+    ) -> Option<usize> {
+        self.bindings(action)
+            .iter()
+            .filter_map(|binding| match binding {
+                Binding::Key(keycode) => pressed_keys.get(keycode),
+                Binding::Mouse(button) => pressed_buttons.get(button),
+            })
+            .cloned()
+            .max()
+    }
+
+    pub fn is_held(
+        &self,
+        action: Action,
+        pressed_keys: &HashMap<VirtualKeyCode, usize>,
+        pressed_buttons: &HashMap<MouseButton, usize>,
+    ) -> bool {
+        self.press_count(action, pressed_keys, pressed_buttons)
+            .is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let map = InputMap::parse(
+            r#"{
+                MoveForward: [Key(Up), Key(W)],
+                Jump: [Mouse(Middle)],
+                Quit: [],
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            map.bindings(Action::MoveForward),
+            &[
+                Binding::Key(VirtualKeyCode::Up),
+                Binding::Key(VirtualKeyCode::W)
+            ]
+        );
+        assert_eq!(
+            map.bindings(Action::Jump),
+            &[Binding::Mouse(MouseButton::Middle)]
+        );
+        assert!(map.bindings(Action::Quit).is_empty());
+        // Unlisted actions keep their defaults
+        assert_eq!(
+            map.bindings(Action::MoveLeft),
+            InputMap::default().bindings(Action::MoveLeft)
+        );
+
+        match InputMap::parse("{Fly: [Key(F)]}") {
+            Err(InputMapError::Parse(_)) => (),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_queries() {
+        let mut map = InputMap::default();
+        map.bind(
+            Action::PlaceBlock,
+            vec![
+                Binding::Mouse(MouseButton::Right),
+                Binding::Key(VirtualKeyCode::E),
+            ],
+        );
+        let mut pressed_keys = HashMap::new();
+        let mut pressed_buttons = HashMap::new();
+        assert!(!map.is_held(Action::PlaceBlock, &pressed_keys, &pressed_buttons));
+
+        pressed_keys.insert(VirtualKeyCode::E, 3);
+        pressed_buttons.insert(MouseButton::Right, 1);
+        assert_eq!(
+            map.press_count(Action::PlaceBlock, &pressed_keys, &pressed_buttons),
+            Some(3)
+        );
+        assert!(!map.is_held(Action::BreakBlock, &pressed_keys, &pressed_buttons));
+
+        assert_eq!(
+            map.actions(Binding::Mouse(MouseButton::Left))
+                .collect::<Vec<_>>(),
+            vec![Action::BreakBlock]
+        );
+        assert_eq!(map.actions(Binding::Key(VirtualKeyCode::Q)).count(), 0);
+    }
+
+    #[test]
+    fn test_load() {
+        assert_eq!(InputMap::load(INPUT_MAP_PATH).unwrap(), InputMap::default());
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{block::BlockType, chunk_map::ChunkMap, geometry::Aabb, types::prelude::*};
+
+/// How far from the eye blocks can be selected
+pub const REACH: f32 = 8.0;
+
+/// Removes the block at `pos` and returns it.
+pub fn break_block(chunk_map: &mut ChunkMap, pos: &Point3f) -> Option<BlockType> {
+    let block = chunk_map.get(pos)?;
+    chunk_map.set(pos, None);
+    Some(block)
+}
+
+/// Places `block` against `face` of the block at `pos`. Returns the position of the new block, or
+/// `None` if the cell is already taken, its chunk isn't loaded, or a solid block would overlap
+/// `player`.
+pub fn place_block(
+    chunk_map: &mut ChunkMap,
+    pos: &Point3f,
+    face: Face,
+    block: BlockType,
+    player: &Aabb,
+) -> Option<Point3f> {
+    let target = pos + face.normal();
+    if chunk_map.chunk(chunk_map.chunk_coords(&target)).is_none()
+        || chunk_map.get(&target).is_some()
+    {
+        return None;
+    }
+    if block.is_solid() && player.intersects(&Aabb::new(target, Vector3f::repeat(0.5))) {
+        return None;
+    }
+    chunk_map.insert(&target, block);
+    Some(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::{body_aabb, EYE_HEIGHT};
+
+    fn stone() -> BlockType {
+        BlockType::named("stone")
+    }
+
+    fn floor() -> ChunkMap {
+        let mut chunk_map = ChunkMap::new(5);
+        for x in -2..=2 {
+            for z in -2..=2 {
+                chunk_map.insert(&Point3f::new(x as f32, 0.0, z as f32), stone());
+            }
+        }
+        chunk_map.update_meshes();
+        chunk_map
+    }
+
+    #[test]
+    fn test_break_block() {
+        let mut chunk_map = floor();
+        let pos = Point3f::new(1.0, 0.0, 1.0);
+        assert_eq!(break_block(&mut chunk_map, &pos), Some(stone()));
+        assert_eq!(chunk_map.get(&pos), None);
+        assert_eq!(break_block(&mut chunk_map, &pos), None);
+        assert_eq!(chunk_map.update_meshes(), vec![(0, 0, 0)]);
+    }
+
+    #[test]
+    fn test_place_block() {
+        let mut chunk_map = floor();
+        let player = body_aabb(&Point3f::new(10.0, 2.1, 10.0));
+        let pos = Point3f::new(0.0, 0.0, 0.0);
+        let dirt = BlockType::named("dirt");
+
+        assert_eq!(
+            place_block(&mut chunk_map, &pos, Face::Top, dirt, &player),
+            Some(Point3f::new(0.0, 1.0, 0.0))
+        );
+        assert_eq!(chunk_map.get(&Point3f::new(0.0, 1.0, 0.0)), Some(dirt));
+        assert_eq!(chunk_map.update_meshes(), vec![(0, 0, 0)]);
+
+        // Occupied
+        let below = Point3f::new(0.0, -1.0, 0.0);
+        chunk_map.insert(&below, stone());
+        assert_eq!(
+            place_block(&mut chunk_map, &pos, Face::Bottom, dirt, &player),
+            None
+        );
+        assert_eq!(chunk_map.get(&below), Some(stone()));
+
+        // Outside of the loaded chunks
+        let edge = Point3f::new(2.0, 0.0, 0.0);
+        assert_eq!(
+            place_block(&mut chunk_map, &edge, Face::Right, dirt, &player),
+            None
+        );
+        assert_eq!(chunk_map.len(), 1);
+    }
+
+    #[test]
+    fn test_place_block_player() {
+        let mut chunk_map = floor();
+        // Standing on top of the floor at (1, 0, 0)
+        let player = body_aabb(&Point3f::new(1.0, 0.5 + EYE_HEIGHT, 0.0));
+        let pos = Point3f::new(1.0, 0.0, 0.0);
+        assert_eq!(
+            place_block(&mut chunk_map, &pos, Face::Top, stone(), &player),
+            None
+        );
+        // Blocks right next to the player are fine
+        assert_eq!(
+            place_block(
+                &mut chunk_map,
+                &Point3f::new(0.0, 0.0, 0.0),
+                Face::Top,
+                stone(),
+                &player
+            ),
+            Some(Point3f::new(0.0, 1.0, 0.0))
+        );
+    }
+}
@@ -11,17 +11,26 @@ use nalgebra as na;
 
 #[macro_use]
 mod utils;
-mod block;
+pub mod atlas;
+pub mod block;
 mod camera;
 pub mod chunk;
+pub mod chunk_map;
 pub mod ecs;
 mod event_handlers;
+pub mod event_source;
 pub mod game;
 pub mod geometry;
+pub mod input;
+pub mod interaction;
 pub mod octree;
+pub mod platform;
+pub mod player;
+pub mod region;
 pub mod renderer;
 pub mod types;
 pub mod vulkan;
 mod vector;
+pub mod worldgen;
 
 pub use crate::types::prelude::*;
@@ -1,20 +1,30 @@
-//! Probably not going to use this for selection for now.
-
 use crate::{
     ecs::{entity::Entity, AabbComponent},
-    geometry::{Aabb, Axis, Ray, AAP},
-    types::{prelude::*, Octants, OctantIndex},
+    geometry::{Aabb, Axis, Frustum, Ray, AAP},
+    types::{prelude::*, OctantIndex, Octants},
     utils::f32,
 };
 use specs::ReadStorage;
 
 const TERMINAL_NODE_MAX_SIZE: usize = 8;
-
+/// Nodes with extents smaller than this aren't split, so that entities piled up in one spot can't
+/// split nodes forever.
+const MIN_NODE_EXTENT: f32 = 1.0 / 64.0;
+
+/// An octree of entities, keyed by their `AabbComponent`s. Entities that lie in one octant of a
+/// node are stored in that child; the rest, such as those crossing the node's center planes or
+/// sticking out of the root, are stored in the node itself. Terminal nodes are split once they
+/// hold more than `TERMINAL_NODE_MAX_SIZE` entities, and nodes are merged back once their subtree
+/// holds that many or fewer.
+///
+/// After an entity's `AabbComponent` changes, it must be moved with `update()`.
 #[derive(Debug, Clone)]
 pub struct Node {
     aabb: Aabb,
     children: Option<Octants<Box<Node>>>,
     entities: Vec<Entity>,
+    /// Number of entities in the node and all of its descendants
+    len: usize,
 }
 
 impl Node {
@@ -23,6 +33,7 @@ impl Node {
             aabb,
             children: None,
             entities: vec![],
+            len: 0,
         }
     }
 
@@ -41,17 +52,14 @@ impl Node {
         child_node_max_size: usize,
     ) -> Node {
         if entities.is_empty() {
-            return Node {
-                aabb,
-                children: None,
-                entities: vec![],
-            };
+            return Node::empty(aabb);
         }
-        if entities.len() <= child_node_max_size {
+        if entities.len() <= child_node_max_size || !can_split(&aabb) {
             return Node {
                 aabb,
                 children: None,
                 entities: entities.to_vec(),
+                len: entities.len(),
             };
         }
         let (node_entities, children) =
@@ -60,6 +68,7 @@ impl Node {
             aabb,
             children: Some(children),
             entities: node_entities,
+            len: entities.len(),
         }
     }
 
@@ -67,21 +76,46 @@ impl Node {
         self.children.is_none()
     }
 
+    pub fn aabb(&self) -> &Aabb {
+        &self.aabb
+    }
+
+    /// Number of entities in the node and all of its descendants.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Index of the child that an entity with bounding box `aabb` belongs in, or `None` if it
+    /// belongs in this node.
+    fn child_index(&self, aabb: &Aabb) -> Option<OctantIndex> {
+        if self.is_terminal() || !self.aabb.contains_aabb(aabb) {
+            return None;
+        }
+        octant(aabb, &self.aabb.center)
+    }
+
     pub fn intersect_entity(
         &self,
         ray: &Ray,
         aabb_storage: &ReadStorage<AabbComponent>,
     ) -> Option<Entity> {
-        self._intersect_entity(ray, aabb_storage).map(|x| x.1)
+        self.closest_entity(ray, aabb_storage).map(|x| x.1)
     }
 
-    fn _intersect_entity(
+    /// Like `intersect_entity`, but also returns the distance along `ray`.
+    pub fn closest_entity(
         &self,
         ray: &Ray,
         aabb_storage: &ReadStorage<AabbComponent>,
     ) -> Option<(f32, Entity)> {
+        // Entities sticking out of the root may be hit even if the root isn't
+        let entity_candidate = ray.closest_entity(&self.entities, aabb_storage);
         if self.is_terminal() {
-            return ray.closest_entity(&self.entities, aabb_storage);
+            return entity_candidate;
         }
 
         let mut a = 0;
@@ -96,19 +130,19 @@ impl Node {
         let center_z = self.aabb.center.z;
 
         if ray.direction.x < 0.0 {
-            ox = center_x - ray.origin.x;
+            ox = 2.0 * center_x - ray.origin.x;
             dx = -ray.direction.x;
             a |= 4;
         }
 
         if ray.direction.y < 0.0 {
-            oy = center_y - ray.origin.y;
+            oy = 2.0 * center_y - ray.origin.y;
             dy = -ray.direction.y;
             a |= 2;
         }
 
         if ray.direction.z < 0.0 {
-            oz = center_z - ray.origin.z;
+            oz = 2.0 * center_z - ray.origin.z;
             dz = -ray.direction.z;
             a |= 1;
         }
@@ -161,9 +195,12 @@ impl Node {
         };
 
         if f32::max_many(&[tx0, ty0, tz0]) < f32::min_many(&[tx1, ty1, tz1]) {
-            return self.proc_subtree(ray, aabb_storage, (tx0, ty0, tz0), (tx1, ty1, tz1), a);
+            return choose_entity(
+                entity_candidate,
+                self.proc_subtree(ray, aabb_storage, (tx0, ty0, tz0), (tx1, ty1, tz1), a),
+            );
         }
-        None
+        entity_candidate
     }
 
     fn proc_subtree(
@@ -362,31 +399,211 @@ impl Node {
     }
 
     pub fn insert(&mut self, entity: Entity, aabb_storage: &ReadStorage<AabbComponent>) {
-        if self.is_terminal() {
-            if self.entities.len() >= TERMINAL_NODE_MAX_SIZE {
-                let (node_entities, children) = partition_children(
-                    &self.entities,
-                    &self.aabb,
-                    aabb_storage,
-                    TERMINAL_NODE_MAX_SIZE,
-                );
-                self.entities = node_entities;
-                self.children = Some(children);
-            } else {
-                self.entities.push(entity);
-            }
+        self.insert_with_aabb(entity, entity.aabb(aabb_storage), aabb_storage);
+    }
+
+    fn insert_with_aabb(
+        &mut self,
+        entity: Entity,
+        aabb: &Aabb,
+        aabb_storage: &ReadStorage<AabbComponent>,
+    ) {
+        self.len += 1;
+        if let Some(i) = self.child_index(aabb) {
+            self.children.as_mut().unwrap()[i].insert_with_aabb(entity, aabb, aabb_storage);
+            return;
+        }
+        self.entities.push(entity);
+        if self.is_terminal()
+            && self.entities.len() > TERMINAL_NODE_MAX_SIZE
+            && can_split(&self.aabb)
+        {
+            let (node_entities, children) = partition_children(
+                &self.entities,
+                &self.aabb,
+                aabb_storage,
+                TERMINAL_NODE_MAX_SIZE,
+            );
+            self.entities = node_entities;
+            self.children = Some(children);
+        }
+    }
+
+    /// Removes `entity`, looking it up by its current `AabbComponent`. Returns false if it isn't in
+    /// the octree.
+    pub fn remove(&mut self, entity: Entity, aabb_storage: &ReadStorage<AabbComponent>) -> bool {
+        self.remove_with_aabb(entity, entity.aabb(aabb_storage))
+    }
+
+    /// Like `remove`, but looks `entity` up by `aabb`, e.g. once its `AabbComponent` is gone.
+    pub fn remove_with_aabb(&mut self, entity: Entity, aabb: &Aabb) -> bool {
+        let removed = if let Some(i) = self.entities.iter().position(|&e| e == entity) {
+            self.entities.swap_remove(i);
+            true
+        } else if let Some(i) = self.child_index(aabb) {
+            self.children.as_mut().unwrap()[i].remove_with_aabb(entity, aabb)
         } else {
-            let oct_idx = octant_index(&(self.aabb.center - entity.aabb(&aabb_storage).center));
-            let mut parent = self;
-            let mut child = &mut parent.children.as_mut().unwrap()[oct_idx];
-            while !child.is_terminal() {
-                parent = child;
-                let oct_idx =
-                    octant_index(&(parent.aabb.center - entity.aabb(&aabb_storage).center));
-                child = &mut parent.children.as_mut().unwrap()[oct_idx];
+            false
+        };
+        if !removed {
+            return false;
+        }
+        self.len -= 1;
+        if !self.is_terminal() && self.len <= TERMINAL_NODE_MAX_SIZE {
+            let mut entities = vec![];
+            self.drain(&mut entities);
+            self.len = entities.len();
+            self.entities = entities;
+        }
+        true
+    }
+
+    /// Moves `entity` after its `AabbComponent` changed from `old_aabb`. Returns false, without
+    /// inserting it, if it isn't in the octree.
+    pub fn update(
+        &mut self,
+        entity: Entity,
+        old_aabb: &Aabb,
+        aabb_storage: &ReadStorage<AabbComponent>,
+    ) -> bool {
+        if !self.remove_with_aabb(entity, old_aabb) {
+            return false;
+        }
+        self.insert(entity, aabb_storage);
+        true
+    }
+
+    /// Moves all entities in the subtree into `entities`, leaving the node empty and terminal.
+    fn drain(&mut self, entities: &mut Vec<Entity>) {
+        self.len = 0;
+        entities.append(&mut self.entities);
+        if let Some(mut children) = self.children.take() {
+            for i in 0..8 {
+                children[i].drain(entities);
+            }
+        }
+    }
+
+    /// Entities whose AABBs overlap `aabb`.
+    pub fn entities_in_aabb(
+        &self,
+        aabb: &Aabb,
+        aabb_storage: &ReadStorage<AabbComponent>,
+    ) -> Vec<Entity> {
+        let mut entities = vec![];
+        self.collect(
+            &|bb: &Aabb| bb.intersects(aabb),
+            aabb_storage,
+            &mut entities,
+        );
+        entities
+    }
+
+    /// Entities whose AABBs are no farther than `radius` from `center`.
+    pub fn entities_in_sphere(
+        &self,
+        center: &Point3f,
+        radius: f32,
+        aabb_storage: &ReadStorage<AabbComponent>,
+    ) -> Vec<Entity> {
+        let mut entities = vec![];
+        self.collect(
+            &|bb: &Aabb| bb.distance(center) <= radius,
+            aabb_storage,
+            &mut entities,
+        );
+        entities
+    }
+
+    /// Entities whose AABBs intersect `frustum`, as tested by `Frustum::intersects_aabb()`.
+    pub fn entities_in_frustum(
+        &self,
+        frustum: &Frustum,
+        aabb_storage: &ReadStorage<AabbComponent>,
+    ) -> Vec<Entity> {
+        let mut entities = vec![];
+        self.collect(
+            &|bb: &Aabb| frustum.intersects_aabb(bb),
+            aabb_storage,
+            &mut entities,
+        );
+        entities
+    }
+
+    /// Adds the entities whose AABBs pass `test` to `entities`. Children whose bounds fail `test`
+    /// are skipped, so `test` must pass for any box containing a box that passes.
+    fn collect<F: Fn(&Aabb) -> bool>(
+        &self,
+        test: &F,
+        aabb_storage: &ReadStorage<AabbComponent>,
+        entities: &mut Vec<Entity>,
+    ) {
+        entities.extend(
+            self.entities
+                .iter()
+                .filter(|entity| test(entity.aabb(aabb_storage))),
+        );
+        if let Some(ref children) = self.children {
+            for i in 0..8 {
+                if test(&children[i].aabb) {
+                    children[i].collect(test, aabb_storage, entities);
+                }
             }
         }
     }
+
+    /// The `k` entities nearest to `point` and their distances from it, nearest first. Distances
+    /// are measured to the entities' AABBs.
+    pub fn nearest_entities(
+        &self,
+        point: &Point3f,
+        k: usize,
+        aabb_storage: &ReadStorage<AabbComponent>,
+    ) -> Vec<(f32, Entity)> {
+        let mut nearest = vec![];
+        if k > 0 {
+            self.collect_nearest(point, k, aabb_storage, &mut nearest);
+        }
+        nearest
+    }
+
+    /// Merges the entities in the subtree into `nearest`, which holds at most `k` entities sorted
+    /// by distance. Children farther away than all of `nearest` are skipped.
+    fn collect_nearest(
+        &self,
+        point: &Point3f,
+        k: usize,
+        aabb_storage: &ReadStorage<AabbComponent>,
+        nearest: &mut Vec<(f32, Entity)>,
+    ) {
+        for &entity in &self.entities {
+            let distance = entity.aabb(aabb_storage).distance(point);
+            if nearest.len() < k || distance < nearest[k - 1].0 {
+                let i = nearest
+                    .iter()
+                    .position(|&(d, _)| distance < d)
+                    .unwrap_or(nearest.len());
+                nearest.insert(i, (distance, entity));
+                nearest.truncate(k);
+            }
+        }
+        if let Some(ref children) = self.children {
+            let mut order: Vec<(f32, usize)> = (0..8)
+                .map(|i| (children[i].aabb.distance(point), i))
+                .collect();
+            order.sort_by(|(d1, _), (d2, _)| d1.partial_cmp(d2).unwrap());
+            for (distance, i) in order {
+                if nearest.len() == k && distance >= nearest[k - 1].0 {
+                    break;
+                }
+                children[i].collect_nearest(point, k, aabb_storage, nearest);
+            }
+        }
+    }
+}
+
+fn can_split(aabb: &Aabb) -> bool {
+    aabb.extents.max() >= MIN_NODE_EXTENT
 }
 
 fn first_node((tx0, ty0, tz0): (f32, f32, f32), (txm, tym, tzm): (f32, f32, f32)) -> usize {
@@ -459,25 +676,30 @@ fn octant_index(v: &Vector3f) -> OctantIndex {
     }
 }
 
+/// The octant around `point` that `aabb` lies in, or `None` if it touches one of the planes
+/// through `point`.
+fn octant(aabb: &Aabb, point: &Point3f) -> Option<OctantIndex> {
+    if AAP::new(Axis::X, point.x).intersects_aabb(aabb)
+        || AAP::new(Axis::Y, point.y).intersects_aabb(aabb)
+        || AAP::new(Axis::Z, point.z).intersects_aabb(aabb)
+    {
+        None
+    } else {
+        Some(octant_index(&(aabb.center - point)))
+    }
+}
+
 fn partition_entities(
     entities: &[Entity],
     point: &Point3f,
     aabb_storage: &ReadStorage<AabbComponent>,
 ) -> (Vec<Entity>, Octants<Vec<Entity>>) {
-    let x_plane = AAP::new(Axis::X, point.x);
-    let y_plane = AAP::new(Axis::Y, point.y);
-    let z_plane = AAP::new(Axis::Z, point.z);
     let mut node_entities = vec![];
     let mut oct_partition: Octants<Vec<Entity>> = Octants::default();
     for &entity in entities {
-        let aabb = entity.aabb(aabb_storage);
-        if x_plane.intersects_aabb(&aabb)
-            || y_plane.intersects_aabb(&aabb)
-            || z_plane.intersects_aabb(&aabb)
-        {
-            node_entities.push(entity);
-        } else {
-            oct_partition[octant_index(&(entity.position_aabb(aabb_storage) - point))].push(entity);
+        match octant(entity.aabb(aabb_storage), point) {
+            Some(i) => oct_partition[i].push(entity),
+            None => node_entities.push(entity),
         }
     }
     (node_entities, oct_partition)
@@ -489,7 +711,12 @@ fn partition_children(
     aabb_storage: &ReadStorage<AabbComponent>,
     child_node_max_size: usize,
 ) -> (Vec<Entity>, Octants<Box<Node>>) {
-    let (node_entities, octants) = partition_entities(entities, &aabb.center, aabb_storage);
+    // Entities sticking out of the node stay in it
+    let (outside, inside): (Vec<Entity>, Vec<Entity>) = entities
+        .iter()
+        .partition(|entity| !aabb.contains_aabb(entity.aabb(aabb_storage)));
+    let (mut node_entities, octants) = partition_entities(&inside, &aabb.center, aabb_storage);
+    node_entities.extend(outside);
     let aabb_octants = aabb.partition();
     let tfl = Box::new(Node::_new_from_entities(
         &octants.tfl,
@@ -560,7 +787,7 @@ mod tests {
     use super::*;
     use crate::ecs::{PrimitiveGeometryComponent, TransformComponent};
     use alga::general::SubsetOf;
-    use rand::{distributions::Uniform, Rng};
+    use rand::{distributions::Uniform, rngs::StdRng, Rng, SeedableRng};
     use specs::World;
 
     struct RayIntersectionParams {
@@ -1098,7 +1325,7 @@ mod tests {
             2,
         );
         let ray = Ray::new(Point3f::new(1.0, 0.0, 10.0), -Vector3f::z());
-        let (_, entity) = bvh._intersect_entity(&ray, &world.read_storage()).unwrap();
+        let (_, entity) = bvh.closest_entity(&ray, &world.read_storage()).unwrap();
         assert!(ray
             .intersect_entity(entity, &world.read_storage())
             .is_some());
@@ -1107,7 +1334,7 @@ mod tests {
             .almost_eq(&Point3f::new(1.0, 0.0, 2.0)));
 
         let ray = Ray::new(Point3f::new(1.0, 1.0, 1.0), Vector3f::new(1.2, -2.3, 4.5));
-        let (_, entity) = bvh._intersect_entity(&ray, &world.read_storage()).unwrap();
+        let (_, entity) = bvh.closest_entity(&ray, &world.read_storage()).unwrap();
         assert!(entity
             .position(&world.read_storage())
             .almost_eq(&Point3f::new(1.0, 1.0, 1.0)));
@@ -1197,7 +1424,7 @@ mod tests {
                 .collect::<Vec<Aabb>>(),
         );
         let bvh = Node::_new_from_entities(&entities, aabb, &world.read_storage(), 8);
-        let entity = bvh._intersect_entity(
+        let entity = bvh.closest_entity(
             &Ray::new(
                 Point3f::new(1.0, 0.0, 10.0),
                 -Vector3f::z_axis().into_inner(),
@@ -1206,7 +1433,7 @@ mod tests {
         );
         assert_eq!(entity, None);
 
-        let entity = bvh._intersect_entity(
+        let entity = bvh.closest_entity(
             &Ray::new(Point3f::origin(), Vector3f::new(1.0, 1.0, 1.0)),
             &world.read_storage(),
         );
@@ -1221,7 +1448,7 @@ mod tests {
             Point3f::new(10.0, 10.0, 10.0),
             Vector3f::new(-1.0, -1.0, -1.0),
         );
-        let entity = bvh._intersect_entity(&ray, &world.read_storage());
+        let entity = bvh.closest_entity(&ray, &world.read_storage());
         assert!(entity
             .unwrap()
             .1
@@ -1229,7 +1456,7 @@ mod tests {
             .almost_eq(&Point3f::new(4.0, 4.0, 4.0)));
 
         let ray = Ray::new(Point3f::new(3.0, -10.0, 2.0), Vector3f::new(0.0, 1.0, 0.0));
-        let entity = bvh._intersect_entity(&ray, &world.read_storage());
+        let entity = bvh.closest_entity(&ray, &world.read_storage());
         println!("{:#?}", entity.unwrap().1.position(&world.read_storage()));
         assert!(entity
             .unwrap()
@@ -1292,4 +1519,310 @@ mod tests {
             );
         }
     }
+
+    /// Checks that entities are stored in the deepest node whose octant holds them, that nodes
+    /// are split and merged as they should be, and that their cached counts are right.
+    fn check_node(node: &Node, aabb_storage: &ReadStorage<AabbComponent>, is_root: bool) {
+        let children_len: usize = match node.children {
+            Some(ref children) => (0..8).map(|i| children[i].len()).sum(),
+            None => 0,
+        };
+        assert_eq!(node.len(), node.entities.len() + children_len);
+        for entity in &node.entities {
+            let aabb = entity.aabb(aabb_storage);
+            assert!(is_root || node.aabb.contains_aabb(aabb));
+            assert_eq!(node.child_index(aabb), None);
+        }
+        match node.children {
+            Some(ref children) => {
+                assert!(node.len() > TERMINAL_NODE_MAX_SIZE);
+                for i in 0..8 {
+                    check_node(&children[i], aabb_storage, false);
+                }
+            }
+            None => {
+                assert!(node.entities.len() <= TERMINAL_NODE_MAX_SIZE || !can_split(&node.aabb))
+            }
+        }
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut world = World::new();
+        world.register::<PrimitiveGeometryComponent>();
+        world.register::<TransformComponent>();
+        world.register::<AabbComponent>();
+
+        let mut octree = Node::empty(Aabb::new(Point3f::origin(), Vector3f::repeat(8.0)));
+        let mut entities = vec![];
+        for i in 0..=TERMINAL_NODE_MAX_SIZE {
+            let entity = Entity::new_unitcube_w(
+                Translation3::from(Vector3f::new(i as f32 - 4.0, 4.0, 4.0)).to_superset(),
+                &world,
+            );
+            octree.insert(entity, &world.read_storage());
+            entities.push(entity);
+        }
+        // The last insert split the root, keeping the new entity
+        assert!(!octree.is_terminal());
+        assert_eq!(octree.len(), TERMINAL_NODE_MAX_SIZE + 1);
+        check_node(&octree, &world.read_storage(), true);
+        let ray = Ray::new(Point3f::new(4.0, 4.0, -10.0), Vector3f::z());
+        assert_eq!(
+            octree.intersect_entity(&ray, &world.read_storage()),
+            Some(entities[TERMINAL_NODE_MAX_SIZE])
+        );
+
+        // Removing one merges the children back
+        assert!(octree.remove(entities[0], &world.read_storage()));
+        assert!(!octree.remove(entities[0], &world.read_storage()));
+        assert!(octree.is_terminal());
+        assert_eq!(octree.len(), TERMINAL_NODE_MAX_SIZE);
+
+        // Moving an entity out of the root keeps it findable
+        let entity = entities[1];
+        let old_aabb = *entity.aabb(&world.read_storage());
+        entity.set_aabb(
+            &mut world.write_storage(),
+            AabbComponent(Aabb::new(
+                Point3f::new(20.0, 0.0, 0.0),
+                Vector3f::repeat(0.5),
+            )),
+        );
+        assert!(octree.update(entity, &old_aabb, &world.read_storage()));
+        let ray = Ray::new(Point3f::new(20.0, 0.0, -10.0), Vector3f::z());
+        assert_eq!(
+            octree.intersect_entity(&ray, &world.read_storage()),
+            Some(entity)
+        );
+        assert!(octree.remove(entity, &world.read_storage()));
+        assert_eq!(octree.intersect_entity(&ray, &world.read_storage()), None);
+    }
+
+    #[test]
+    fn test_random_edits() {
+        let aabb_size = 10.0;
+        let position_dist = Uniform::new(-aabb_size - 1.0, aabb_size + 1.0);
+        let direction_dist = Uniform::new(-1.0, 1.0);
+        let center = Point3f::new(2.0, -3.0, 1.0);
+
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let world = new_aabb_world();
+            // Off-center, so that rays are reflected around a point other than the origin
+            let mut octree = Node::empty(Aabb::new(center, Vector3f::repeat(aabb_size)));
+            let mut entities: Vec<Entity> = vec![];
+            for _ in 0..300 {
+                let choice = rng.gen_range(0, 4);
+                if choice < 2 || entities.is_empty() {
+                    let entity = new_random_entity(&mut rng, &world, &center);
+                    octree.insert(entity, &world.read_storage());
+                    entities.push(entity);
+                } else if choice == 2 {
+                    let entity = entities.swap_remove(rng.gen_range(0, entities.len()));
+                    assert!(octree.remove(entity, &world.read_storage()));
+                } else {
+                    let entity = entities[rng.gen_range(0, entities.len())];
+                    let old_aabb = *entity.aabb(&world.read_storage());
+                    entity.set_aabb(
+                        &mut world.write_storage(),
+                        AabbComponent(random_aabb(&mut rng, &center)),
+                    );
+                    assert!(octree.update(entity, &old_aabb, &world.read_storage()));
+                }
+
+                let storage = world.read_storage();
+                assert_eq!(octree.len(), entities.len());
+                check_node(&octree, &storage, true);
+                for _ in 0..5 {
+                    let ray = Ray::new(
+                        center
+                            + Vector3f::new(
+                                rng.sample(position_dist),
+                                rng.sample(position_dist),
+                                rng.sample(position_dist),
+                            ),
+                        Vector3f::new(
+                            rng.sample(direction_dist),
+                            rng.sample(direction_dist),
+                            rng.sample(direction_dist),
+                        ),
+                    );
+                    let expected = ray.intersect_entities(&entities, &storage);
+                    match (octree.closest_entity(&ray, &storage), expected) {
+                        (None, None) => (),
+                        (Some((t, entity)), Some((_, (expected_t, _)))) => {
+                            assert!(entities.contains(&entity));
+                            assert!((t - expected_t).abs() < 1e-4, "{} != {}", t, expected_t);
+                        }
+                        (hit, expected) => panic!("{:?} != {:?}", hit, expected),
+                    }
+                }
+            }
+
+            for entity in entities {
+                assert!(octree.remove(entity, &world.read_storage()));
+            }
+            assert!(octree.is_empty());
+            assert!(octree.is_terminal());
+        }
+    }
+
+    /// A world with the storages that entities in an octree need
+    fn new_aabb_world() -> World {
+        let mut world = World::new();
+        world.register::<PrimitiveGeometryComponent>();
+        world.register::<TransformComponent>();
+        world.register::<AabbComponent>();
+        world
+    }
+
+    /// A random box around a point within 11 of `center` along each axis, so that some stick out
+    /// of an octree of extent 10 around `center`.
+    fn random_aabb(rng: &mut StdRng, center: &Point3f) -> Aabb {
+        let position_dist = Uniform::new(-11.0, 11.0);
+        let extent_dist = Uniform::new(0.05, 1.5);
+        Aabb::new(
+            center
+                + Vector3f::new(
+                    rng.sample(position_dist),
+                    rng.sample(position_dist),
+                    rng.sample(position_dist),
+                ),
+            Vector3f::new(
+                rng.sample(extent_dist),
+                rng.sample(extent_dist),
+                rng.sample(extent_dist),
+            ),
+        )
+    }
+
+    fn new_random_entity(rng: &mut StdRng, world: &World, center: &Point3f) -> Entity {
+        let entity = Entity::new_unitcube_w(Transform3f::identity(), world);
+        entity.set_aabb(
+            &mut world.write_storage(),
+            AabbComponent(random_aabb(rng, center)),
+        );
+        entity
+    }
+
+    /// A world of `n` random boxes, some sticking out of the octree, and the octree holding them.
+    fn new_random_octree(rng: &mut StdRng, n: usize) -> (World, Vec<Entity>, Node) {
+        let world = new_aabb_world();
+        let center = Point3f::origin();
+        let entities: Vec<Entity> = (0..n)
+            .map(|_| new_random_entity(rng, &world, &center))
+            .collect();
+        let octree = Node::new_from_entities(
+            &entities,
+            Aabb::new(center, Vector3f::repeat(10.0)),
+            &world.read_storage(),
+        );
+        (world, entities, octree)
+    }
+
+    fn sorted(mut entities: Vec<Entity>) -> Vec<Entity> {
+        entities.sort_by_key(|entity| entity.entity.id());
+        entities
+    }
+
+    fn random_point(rng: &mut StdRng) -> Point3f {
+        let dist = Uniform::new(-12.0, 12.0);
+        Point3f::new(rng.sample(dist), rng.sample(dist), rng.sample(dist))
+    }
+
+    #[test]
+    fn test_range_queries() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let (world, entities, octree) = new_random_octree(&mut rng, 500);
+        let storage = world.read_storage();
+        let extent_dist = Uniform::new(0.1, 6.0);
+        for _ in 0..50 {
+            let aabb = Aabb::new(
+                random_point(&mut rng),
+                Vector3f::new(
+                    rng.sample(extent_dist),
+                    rng.sample(extent_dist),
+                    rng.sample(extent_dist),
+                ),
+            );
+            let expected = entities
+                .iter()
+                .cloned()
+                .filter(|entity| entity.aabb(&storage).intersects(&aabb))
+                .collect();
+            assert_eq!(
+                sorted(octree.entities_in_aabb(&aabb, &storage)),
+                sorted(expected)
+            );
+
+            let center = random_point(&mut rng);
+            let radius = rng.sample(extent_dist);
+            let expected = entities
+                .iter()
+                .cloned()
+                .filter(|entity| entity.aabb(&storage).distance(&center) <= radius)
+                .collect();
+            assert_eq!(
+                sorted(octree.entities_in_sphere(&center, radius, &storage)),
+                sorted(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn test_frustum_query() {
+        use crate::na::geometry::Perspective3;
+
+        let mut rng = StdRng::seed_from_u64(2);
+        let (world, entities, octree) = new_random_octree(&mut rng, 500);
+        let storage = world.read_storage();
+        for _ in 0..50 {
+            let eye = random_point(&mut rng);
+            let target = random_point(&mut rng);
+            let fov = rng.gen_range(30.0, 100.0);
+            let proj = Perspective3::new(1.5, f32::to_radians(fov), 0.1, 20.0).to_homogeneous();
+            let view = Matrix4f::look_at_rh(&eye, &target, &Vector3f::y_axis());
+            let frustum = Frustum::from_matrix(&(proj * view));
+            let expected = entities
+                .iter()
+                .cloned()
+                .filter(|entity| frustum.intersects_aabb(entity.aabb(&storage)))
+                .collect();
+            assert_eq!(
+                sorted(octree.entities_in_frustum(&frustum, &storage)),
+                sorted(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn test_nearest_entities() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let (world, entities, octree) = new_random_octree(&mut rng, 500);
+        let storage = world.read_storage();
+        for _ in 0..50 {
+            let point = random_point(&mut rng);
+            let k = rng.gen_range(0, 20);
+            let mut expected: Vec<f32> = entities
+                .iter()
+                .map(|entity| entity.aabb(&storage).distance(&point))
+                .collect();
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            expected.truncate(k);
+
+            let nearest = octree.nearest_entities(&point, k, &storage);
+            for &(distance, entity) in &nearest {
+                assert_eq!(entity.aabb(&storage).distance(&point), distance);
+            }
+            let distances: Vec<f32> = nearest.iter().map(|&(distance, _)| distance).collect();
+            assert_eq!(distances, expected);
+        }
+        assert_eq!(
+            octree
+                .nearest_entities(&Point3f::origin(), entities.len() + 1, &storage)
+                .len(),
+            entities.len()
+        );
+    }
 }
@@ -0,0 +1,67 @@
+use crate::event_source::EventSource;
+use failure::Error;
+use std::os::raw::c_void;
+use winit::{MouseButton, VirtualKeyCode};
+
+mod scripted;
+mod winit_platform;
+
+pub use self::{scripted::ScriptedPlatform, winit_platform::WinitPlatform};
+
+/// Input and window events, independent of the windowing library that produced them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlatformEvent {
+    Key {
+        keycode: VirtualKeyCode,
+        pressed: bool,
+    },
+    MouseButton {
+        button: MouseButton,
+        pressed: bool,
+    },
+    /// Raw mouse movement, in pixels
+    MouseMotion {
+        dx: f64,
+        dy: f64,
+    },
+    /// Lines scrolled. Positive is away from the user.
+    MouseWheel {
+        lines: f32,
+    },
+    Focused(bool),
+    /// New size of the window, in logical pixels
+    Resized {
+        width: u32,
+        height: u32,
+    },
+    CloseRequested,
+}
+
+/// Raw handles of a window, which a renderer can create a surface from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WindowHandle {
+    #[cfg(target_os = "windows")]
+    Win32 {
+        hinstance: *const c_void,
+        hwnd: *const c_void,
+    },
+    #[cfg(all(unix, not(target_os = "macos")))]
+    Xlib { display: *const c_void, window: u64 },
+    #[cfg(all(unix, not(target_os = "macos")))]
+    Wayland {
+        display: *const c_void,
+        surface: *const c_void,
+    },
+}
+
+/// Owns the window, if any, and the event loop, which it polls for events.
+pub trait Platform: EventSource {
+    /// Grabs and hides the cursor, or releases and shows it.
+    fn set_cursor_grab(&mut self, grab: bool) -> Result<(), Error>;
+
+    /// `None` if the platform has no window.
+    fn window_handle(&self) -> Option<WindowHandle>;
+
+    /// Size of the window, in logical pixels
+    fn window_size(&self) -> (u32, u32);
+}
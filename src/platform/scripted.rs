@@ -0,0 +1,47 @@
+use crate::{
+    event_source::{EventSource, ScriptedEvents},
+    platform::{Platform, PlatformEvent, WindowHandle},
+};
+use failure::Error;
+
+/// A platform without a window whose events come from `ScriptedEvents`.
+#[derive(Debug, Default)]
+pub struct ScriptedPlatform {
+    pub events: ScriptedEvents,
+    cursor_grabbed: bool,
+}
+
+impl ScriptedPlatform {
+    /// `frames[i]` holds the events returned by the `i`th poll.
+    pub fn new(frames: Vec<Vec<PlatformEvent>>) -> ScriptedPlatform {
+        ScriptedPlatform {
+            events: ScriptedEvents::new(frames),
+            cursor_grabbed: false,
+        }
+    }
+
+    pub fn cursor_grabbed(&self) -> bool {
+        self.cursor_grabbed
+    }
+}
+
+impl EventSource for ScriptedPlatform {
+    fn poll_events(&mut self, callback: &mut dyn FnMut(PlatformEvent)) {
+        self.events.poll_events(callback);
+    }
+}
+
+impl Platform for ScriptedPlatform {
+    fn set_cursor_grab(&mut self, grab: bool) -> Result<(), Error> {
+        self.cursor_grabbed = grab;
+        Ok(())
+    }
+
+    fn window_handle(&self) -> Option<WindowHandle> {
+        None
+    }
+
+    fn window_size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{
+    event_source::EventSource,
+    platform::{Platform, PlatformEvent, WindowHandle},
+};
+use failure::{err_msg, Error};
+use winit::{
+    dpi::LogicalSize, DeviceEvent, ElementState, Event, EventsLoop, KeyboardInput, MouseButton,
+    MouseScrollDelta, Window, WindowBuilder, WindowEvent,
+};
+
+/// Pixels scrolled by a touchpad that count as one line of a mouse wheel
+const PIXELS_PER_LINE: f64 = 20.0;
+
+/// Raw button ids are 1 for left, 2 for middle and 3 for right on every platform we support.
+fn mouse_button(button: u32) -> MouseButton {
+    match button {
+        1 => MouseButton::Left,
+        2 => MouseButton::Middle,
+        3 => MouseButton::Right,
+        _ => MouseButton::Other(button as u8),
+    }
+}
+
+fn translate_device_event(event: DeviceEvent) -> Option<PlatformEvent> {
+    match event {
+        DeviceEvent::Key(KeyboardInput {
+            virtual_keycode: Some(keycode),
+            state,
+            ..
+        }) => Some(PlatformEvent::Key {
+            keycode,
+            pressed: state == ElementState::Pressed,
+        }),
+        DeviceEvent::Button { button, state } => Some(PlatformEvent::MouseButton {
+            button: mouse_button(button),
+            pressed: state == ElementState::Pressed,
+        }),
+        DeviceEvent::MouseMotion { delta: (dx, dy) } => Some(PlatformEvent::MouseMotion { dx, dy }),
+        DeviceEvent::MouseWheel { delta } => Some(PlatformEvent::MouseWheel {
+            lines: match delta {
+                MouseScrollDelta::LineDelta(_, dy) => dy,
+                MouseScrollDelta::PixelDelta(pos) => (pos.y / PIXELS_PER_LINE) as f32,
+            },
+        }),
+        _ => None,
+    }
+}
+
+fn translate_window_event(event: WindowEvent) -> Option<PlatformEvent> {
+    match event {
+        WindowEvent::CloseRequested => Some(PlatformEvent::CloseRequested),
+        WindowEvent::Focused(focused) => Some(PlatformEvent::Focused(focused)),
+        WindowEvent::Resized(LogicalSize { width, height }) => Some(PlatformEvent::Resized {
+            width: width as u32,
+            height: height as u32,
+        }),
+        _ => None,
+    }
+}
+
+/// A desktop window and its event loop.
+pub struct WinitPlatform {
+    events_loop: EventsLoop,
+    window: Window,
+    just_started: bool,
+}
+
+impl WinitPlatform {
+    pub fn new(title: &str, width: u32, height: u32) -> Result<WinitPlatform, Error> {
+        let events_loop = EventsLoop::new();
+        let window = WindowBuilder::new()
+            .with_title(title)
+            .with_resizable(true)
+            .with_dimensions(LogicalSize::new(f64::from(width), f64::from(height)))
+            .build(&events_loop)?;
+        Ok(WinitPlatform {
+            events_loop,
+            window,
+            just_started: true,
+        })
+    }
+}
+
+impl EventSource for WinitPlatform {
+    fn poll_events(&mut self, callback: &mut dyn FnMut(PlatformEvent)) {
+        let just_started = &mut self.just_started;
+        self.events_loop.poll_events(|event| {
+            let event = match event {
+                Event::DeviceEvent { event, .. } => translate_device_event(event),
+                Event::WindowEvent { event, .. } => translate_window_event(event),
+                _ => None,
+            };
+            match event {
+                // When the window is first created, a resized event is sent
+                Some(PlatformEvent::Resized { .. }) if *just_started => *just_started = false,
+                Some(event) => callback(event),
+                None => (),
+            }
+        });
+    }
+}
+
+impl Platform for WinitPlatform {
+    fn set_cursor_grab(&mut self, grab: bool) -> Result<(), Error> {
+        self.window.grab_cursor(grab).map_err(err_msg)?;
+        self.window.hide_cursor(grab);
+        Ok(())
+    }
+
+    #[cfg(target_os = "windows")]
+    fn window_handle(&self) -> Option<WindowHandle> {
+        use winapi::um::libloaderapi::GetModuleHandleW;
+        use winit::os::windows::WindowExt;
+
+        let hinstance = unsafe { GetModuleHandleW(std::ptr::null()) };
+        Some(WindowHandle::Win32 {
+            hinstance: hinstance as *const _,
+            hwnd: self.window.get_hwnd() as *const _,
+        })
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn window_handle(&self) -> Option<WindowHandle> {
+        use winit::os::unix::WindowExt;
+
+        if let (Some(display), Some(surface)) = (
+            self.window.get_wayland_display(),
+            self.window.get_wayland_surface(),
+        ) {
+            return Some(WindowHandle::Wayland {
+                display: display as *const _,
+                surface: surface as *const _,
+            });
+        }
+        match (
+            self.window.get_xlib_display(),
+            self.window.get_xlib_window(),
+        ) {
+            (Some(display), Some(window)) => Some(WindowHandle::Xlib {
+                display: display as *const _,
+                window,
+            }),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(any(target_os = "windows", all(unix, not(target_os = "macos")))))]
+    fn window_handle(&self) -> Option<WindowHandle> {
+        None
+    }
+
+    fn window_size(&self) -> (u32, u32) {
+        let LogicalSize { width, height } = self.window.get_inner_size().unwrap();
+        (width as u32, height as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::{dpi::LogicalPosition, VirtualKeyCode};
+
+    #[test]
+    fn test_translate() {
+        assert_eq!(
+            translate_device_event(DeviceEvent::Button {
+                button: 3,
+                state: ElementState::Pressed,
+            }),
+            Some(PlatformEvent::MouseButton {
+                button: MouseButton::Right,
+                pressed: true,
+            })
+        );
+        assert_eq!(
+            translate_device_event(DeviceEvent::Button {
+                button: 8,
+                state: ElementState::Released,
+            }),
+            Some(PlatformEvent::MouseButton {
+                button: MouseButton::Other(8),
+                pressed: false,
+            })
+        );
+        assert_eq!(
+            translate_device_event(DeviceEvent::MouseWheel {
+                delta: MouseScrollDelta::PixelDelta(LogicalPosition::new(0.0, 40.0)),
+            }),
+            Some(PlatformEvent::MouseWheel { lines: 2.0 })
+        );
+        assert_eq!(
+            translate_device_event(DeviceEvent::Key(KeyboardInput {
+                scancode: 0,
+                state: ElementState::Released,
+                virtual_keycode: Some(VirtualKeyCode::Q),
+                modifiers: Default::default(),
+            })),
+            Some(PlatformEvent::Key {
+                keycode: VirtualKeyCode::Q,
+                pressed: false,
+            })
+        );
+        assert_eq!(
+            translate_window_event(WindowEvent::Resized(LogicalSize::new(800.0, 600.0))),
+            Some(PlatformEvent::Resized {
+                width: 800,
+                height: 600,
+            })
+        );
+        assert_eq!(translate_window_event(WindowEvent::Refresh), None);
+    }
+}
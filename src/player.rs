@@ -0,0 +1,505 @@
+use crate::{chunk_map::ChunkMap, geometry::Aabb, types::prelude::*, utils::point3f};
+use failure_derive::Fail;
+use serde::Deserialize;
+use std::{fs, io, path::Path};
+
+/// Path of the movement speeds loaded at startup
+pub const MOVEMENT_SPEEDS_PATH: &str = "assets/movement.ron";
+
+/// Size of the player's body along x and z
+pub const PLAYER_WIDTH: f32 = 0.6;
+pub const PLAYER_HEIGHT: f32 = 1.8;
+/// Height of the camera above the bottom of the player's body
+pub const EYE_HEIGHT: f32 = 1.6;
+/// In blocks per second squared
+pub const GRAVITY: f32 = 32.0;
+/// Vertical speed at the start of a jump, in blocks per second. Enough to clear one block.
+pub const JUMP_SPEED: f32 = 9.0;
+/// Fastest the player can fall, in blocks per second
+pub const TERMINAL_SPEED: f32 = 60.0;
+/// Default walking speed, in blocks per second
+pub const WALK_SPEED: f32 = 4.3;
+
+/// Gap left between the player and the blocks it collides with, so that it doesn't end up
+/// overlapping them due to rounding.
+const SKIN: f32 = 1e-4;
+
+/// Returns the player's body, given the position of the camera.
+pub fn body_aabb(eye: &Point3f) -> Aabb {
+    let extents = Vector3f::new(PLAYER_WIDTH, PLAYER_HEIGHT, PLAYER_WIDTH) / 2.0;
+    let center = Point3f::new(eye.x, eye.y - EYE_HEIGHT + extents.y, eye.z);
+    Aabb::new(center, extents)
+}
+
+/// Returns the positions of the blocks whose cells overlap `[min, max]` along each axis.
+fn cells(min: &Point3f, max: &Point3f) -> impl Iterator<Item = Point3f> {
+    // Cells that only touch the range aren't included
+    let lo = |x: f32| (x + 0.5 + SKIN).floor() as i32;
+    let hi = |x: f32| (x + 0.5 - SKIN).ceil() as i32;
+    let (x0, x1) = (lo(min.x), hi(max.x));
+    let (y0, y1) = (lo(min.y), hi(max.y));
+    let (z0, z1) = (lo(min.z), hi(max.z));
+    (x0..x1).flat_map(move |x| {
+        (y0..y1)
+            .flat_map(move |y| (z0..z1).map(move |z| Point3f::new(x as f32, y as f32, z as f32)))
+    })
+}
+
+/// Moves `aabb` by `delta` along `axis`, stopping in front of the first solid block in the way.
+/// Blocks that `aabb` already overlaps are ignored so that it can't get stuck in them. Returns the
+/// distance moved.
+fn sweep_axis(chunk_map: &ChunkMap, aabb: &Aabb, axis: usize, delta: f32) -> f32 {
+    if delta == 0.0 {
+        return 0.0;
+    }
+    let mut min = aabb.min();
+    let mut max = aabb.max();
+    if delta > 0.0 {
+        max[axis] += delta;
+    } else {
+        min[axis] += delta;
+    }
+
+    let mut moved = delta;
+    for pos in cells(&min, &max) {
+        match chunk_map.get(&pos) {
+            Some(block) if block.is_solid() => (),
+            _ => continue,
+        }
+        if delta > 0.0 {
+            let gap = pos[axis] - 0.5 - aabb.max()[axis];
+            if gap >= -SKIN {
+                moved = moved.min((gap - SKIN).max(0.0));
+            }
+        } else {
+            let gap = aabb.min()[axis] - (pos[axis] + 0.5);
+            if gap >= -SKIN {
+                moved = moved.max(-(gap - SKIN).max(0.0));
+            }
+        }
+    }
+    moved
+}
+
+/// Moves `aabb` by `delta` one axis at a time, y first, stopping at solid blocks. Returns the
+/// distance moved along each axis and whether movement along it was blocked.
+pub fn sweep(chunk_map: &ChunkMap, aabb: &Aabb, delta: &Vector3f) -> (Vector3f, [bool; 3]) {
+    let mut aabb = *aabb;
+    let mut moved = Vector3f::zeros();
+    let mut blocked = [false; 3];
+    for &axis in &[1, 0, 2] {
+        moved[axis] = sweep_axis(chunk_map, &aabb, axis, delta[axis]);
+        blocked[axis] = moved[axis] != delta[axis];
+        aabb.center[axis] += moved[axis];
+    }
+    (moved, blocked)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovementMode {
+    /// Falls and collides with solid blocks
+    Walk,
+    /// Creative flight. Doesn't fall, but still collides with solid blocks.
+    Fly,
+    /// Flies through everything
+    Spectator,
+}
+
+impl MovementMode {
+    /// The mode that comes after this one when cycling through modes
+    pub fn next(self) -> MovementMode {
+        match self {
+            MovementMode::Walk => MovementMode::Fly,
+            MovementMode::Fly => MovementMode::Spectator,
+            MovementMode::Spectator => MovementMode::Walk,
+        }
+    }
+}
+
+#[derive(Fail, Debug)]
+pub enum MovementSpeedsError {
+    #[fail(display = "{}", _0)]
+    Io(#[cause] io::Error),
+    #[fail(display = "{}", _0)]
+    Parse(#[cause] ron::de::Error),
+}
+
+impl From<io::Error> for MovementSpeedsError {
+    fn from(err: io::Error) -> MovementSpeedsError {
+        MovementSpeedsError::Io(err)
+    }
+}
+
+impl From<ron::de::Error> for MovementSpeedsError {
+    fn from(err: ron::de::Error) -> MovementSpeedsError {
+        MovementSpeedsError::Parse(err)
+    }
+}
+
+/// In blocks per second
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct MovementSpeed {
+    pub normal: f32,
+    pub sprint: f32,
+}
+
+/// Speed of each movement mode. Modes left out of a speeds file keep their default speeds.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct MovementSpeeds {
+    pub walk: MovementSpeed,
+    pub fly: MovementSpeed,
+    pub spectator: MovementSpeed,
+}
+
+impl Default for MovementSpeeds {
+    fn default() -> MovementSpeeds {
+        MovementSpeeds {
+            walk: MovementSpeed {
+                normal: WALK_SPEED,
+                sprint: 5.6,
+            },
+            fly: MovementSpeed {
+                normal: 10.9,
+                sprint: 21.6,
+            },
+            spectator: MovementSpeed {
+                normal: 15.0,
+                sprint: 40.0,
+            },
+        }
+    }
+}
+
+impl MovementSpeeds {
+    /// Loads speeds from the RON file at `path`, or returns the default speeds if there's no file
+    /// there.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<MovementSpeeds, MovementSpeedsError> {
+        match fs::read_to_string(path) {
+            Ok(s) => MovementSpeeds::parse(&s),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(MovementSpeeds::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<MovementSpeeds, MovementSpeedsError> {
+        Ok(ron::de::from_str(s)?)
+    }
+
+    pub fn speed(&self, mode: MovementMode, sprinting: bool) -> f32 {
+        let speed = match mode {
+            MovementMode::Walk => self.walk,
+            MovementMode::Fly => self.fly,
+            MovementMode::Spectator => self.spectator,
+        };
+        if sprinting {
+            speed.sprint
+        } else {
+            speed.normal
+        }
+    }
+}
+
+/// A player that moves through the world according to its `MovementMode`.
+#[derive(Debug, Clone)]
+pub struct Player {
+    /// Center of the bottom of the player's body
+    pub pos: Point3f,
+    /// `pos` before the last step
+    pub prev_pos: Point3f,
+    /// In blocks per second
+    pub velocity: Vector3f,
+    pub on_ground: bool,
+    mode: MovementMode,
+}
+
+impl Player {
+    pub fn new(pos: Point3f) -> Player {
+        Player {
+            pos,
+            prev_pos: pos,
+            velocity: Vector3f::zeros(),
+            on_ground: false,
+            mode: MovementMode::Walk,
+        }
+    }
+
+    pub fn mode(&self) -> MovementMode {
+        self.mode
+    }
+
+    /// Switches to `mode`, stopping the player.
+    pub fn set_mode(&mut self, mode: MovementMode) {
+        self.mode = mode;
+        self.velocity = Vector3f::zeros();
+        self.on_ground = false;
+    }
+
+    /// Position of the camera
+    pub fn eye(&self) -> Point3f {
+        self.pos + Vector3f::new(0.0, EYE_HEIGHT, 0.0)
+    }
+
+    /// Position of the camera a fraction `alpha` of the way through the last step
+    pub fn interpolated_eye(&self, alpha: f32) -> Point3f {
+        point3f::clerp(&self.prev_pos, &self.pos, alpha) + Vector3f::new(0.0, EYE_HEIGHT, 0.0)
+    }
+
+    /// Moves the player to `pos` without interpolating from where it was.
+    pub fn teleport(&mut self, pos: Point3f) {
+        self.pos = pos;
+        self.prev_pos = pos;
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        body_aabb(&self.eye())
+    }
+
+    /// Starts a jump if the player is walking on something.
+    pub fn jump(&mut self) {
+        if self.mode == MovementMode::Walk && self.on_ground {
+            self.velocity.y = JUMP_SPEED;
+            self.on_ground = false;
+        }
+    }
+
+    /// Advances the player by `dt` seconds. `movement` is the velocity the player wants to move
+    /// at. When walking, its y component is ignored and gravity is applied instead.
+    pub fn step(&mut self, chunk_map: &ChunkMap, movement: &Vector3f, dt: f32) {
+        self.prev_pos = self.pos;
+        match self.mode {
+            MovementMode::Walk => {
+                self.velocity.x = movement.x;
+                self.velocity.z = movement.z;
+                self.velocity.y = (self.velocity.y - GRAVITY * dt).max(-TERMINAL_SPEED);
+            }
+            MovementMode::Fly => self.velocity = *movement,
+            MovementMode::Spectator => {
+                self.velocity = *movement;
+                self.pos += self.velocity * dt;
+                return;
+            }
+        }
+
+        let delta = self.velocity * dt;
+        let (moved, blocked) = sweep(chunk_map, &self.aabb(), &delta);
+        self.pos += moved;
+        for (axis, &blocked) in blocked.iter().enumerate() {
+            if blocked {
+                self.velocity[axis] = 0.0;
+            }
+        }
+        self.on_ground = self.mode == MovementMode::Walk && blocked[1] && delta.y < 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block::BlockType, game::TICK_TIME};
+
+    /// Whether `a` and `b` are equal up to the gap left by collisions
+    fn near(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    fn stone() -> BlockType {
+        BlockType::named("stone")
+    }
+
+    /// A floor whose top is at y = 0.5, spanning -4..=4 along x and z
+    fn floor() -> ChunkMap {
+        let mut chunk_map = ChunkMap::new(9);
+        for x in -4..=4 {
+            for z in -4..=4 {
+                chunk_map.insert(&Point3f::new(x as f32, 0.0, z as f32), stone());
+            }
+        }
+        chunk_map
+    }
+
+    fn run(player: &mut Player, chunk_map: &ChunkMap, walk: &Vector3f, steps: usize) {
+        for _ in 0..steps {
+            player.step(chunk_map, walk, TICK_TIME);
+        }
+    }
+
+    #[test]
+    fn test_land_on_floor() {
+        let chunk_map = floor();
+        let mut player = Player::new(Point3f::new(0.0, 3.0, 0.0));
+        run(&mut player, &chunk_map, &Vector3f::zeros(), 60);
+        assert!(player.on_ground);
+        assert!(near(player.pos.y, 0.5));
+        assert_eq!(player.velocity, Vector3f::zeros());
+
+        // Standing still keeps the player on the ground
+        run(&mut player, &chunk_map, &Vector3f::zeros(), 10);
+        assert!(player.on_ground);
+        assert!(near(player.pos.y, 0.5));
+    }
+
+    #[test]
+    fn test_walk_into_wall() {
+        let mut chunk_map = floor();
+        for y in 1..=2 {
+            for z in -4..=4 {
+                chunk_map.insert(&Point3f::new(2.0, y as f32, z as f32), stone());
+            }
+        }
+        let mut player = Player::new(Point3f::new(0.0, 0.5, 0.0));
+        // Walking diagonally slides along the wall
+        let walk = Vector3f::new(WALK_SPEED, 0.0, WALK_SPEED);
+        run(&mut player, &chunk_map, &walk, 30);
+        assert!(near(player.pos.x, 1.5 - PLAYER_WIDTH / 2.0 - SKIN));
+        assert!(player.pos.z > 1.0);
+        assert!(player.on_ground);
+        assert!(!player.aabb().intersects(&Aabb::new(
+            Point3f::new(2.0, 1.0, 0.0),
+            Vector3f::repeat(0.5)
+        )));
+    }
+
+    #[test]
+    fn test_step_off_ledge() {
+        let chunk_map = floor();
+        let mut player = Player::new(Point3f::new(3.0, 0.5, 0.0));
+        run(
+            &mut player,
+            &chunk_map,
+            &Vector3f::new(WALK_SPEED, 0.0, 0.0),
+            30,
+        );
+        assert!(player.pos.x > 4.5 + PLAYER_WIDTH / 2.0);
+        assert!(player.pos.y < 0.5);
+        assert!(!player.on_ground);
+        assert!(player.velocity.y < 0.0);
+    }
+
+    #[test]
+    fn test_jump() {
+        let mut chunk_map = floor();
+        chunk_map.insert(&Point3f::new(1.0, 1.0, 0.0), stone());
+        let mut player = Player::new(Point3f::new(0.0, 0.5, 0.0));
+        run(&mut player, &chunk_map, &Vector3f::zeros(), 1);
+        assert!(player.on_ground);
+
+        // Can't walk onto the block without jumping
+        let walk = Vector3f::new(WALK_SPEED, 0.0, 0.0);
+        run(&mut player, &chunk_map, &walk, 30);
+        assert!(near(player.pos.y, 0.5));
+        assert!(player.pos.x < 0.5);
+
+        player.jump();
+        assert!(!player.on_ground);
+        run(&mut player, &chunk_map, &walk, 30);
+        assert!(player.on_ground);
+        assert!(near(player.pos.y, 1.5));
+        assert!(player.pos.x > 0.5);
+
+        // Jumping in mid-air does nothing
+        player.velocity.y = -1.0;
+        player.on_ground = false;
+        player.jump();
+        assert_eq!(player.velocity.y, -1.0);
+    }
+
+    #[test]
+    fn test_hit_ceiling() {
+        let mut chunk_map = floor();
+        chunk_map.insert(&Point3f::new(0.0, 3.0, 0.0), stone());
+        let mut player = Player::new(Point3f::new(0.0, 0.5, 0.0));
+        run(&mut player, &chunk_map, &Vector3f::zeros(), 1);
+        player.jump();
+        run(&mut player, &chunk_map, &Vector3f::zeros(), 3);
+        // The ceiling is at y = 2.5, so the player's head stops just under it
+        assert!(player.aabb().max().y <= 2.5);
+        assert!(player.velocity.y <= 0.0);
+
+        // Being pushed against the ceiling doesn't count as standing on it
+        let mut player = Player::new(Point3f::new(0.0, 2.5 - PLAYER_HEIGHT - SKIN / 2.0, 0.0));
+        player.velocity.y = 5.0;
+        run(&mut player, &chunk_map, &Vector3f::zeros(), 1);
+        assert_eq!(player.velocity.y, 0.0);
+        assert!(!player.on_ground);
+    }
+
+    #[test]
+    fn test_fly() {
+        let mut chunk_map = floor();
+        chunk_map.insert(&Point3f::new(2.0, 3.0, 0.0), stone());
+        let mut player = Player::new(Point3f::new(0.0, 2.5, 0.0));
+        player.set_mode(MovementMode::Fly);
+
+        // No gravity
+        run(&mut player, &chunk_map, &Vector3f::zeros(), 30);
+        assert!(near(player.pos.y, 2.5));
+        assert!(!player.on_ground);
+        player.jump();
+        assert_eq!(player.velocity, Vector3f::zeros());
+
+        // Still collides
+        run(&mut player, &chunk_map, &Vector3f::new(5.0, 0.0, 0.0), 30);
+        assert!(near(player.pos.x, 1.5 - PLAYER_WIDTH / 2.0));
+        run(&mut player, &chunk_map, &Vector3f::new(0.0, -5.0, 0.0), 30);
+        assert!(near(player.pos.y, 0.5));
+        assert!(!player.on_ground);
+    }
+
+    #[test]
+    fn test_spectator() {
+        let chunk_map = floor();
+        let mut player = Player::new(Point3f::new(0.0, 2.5, 0.0));
+        player.set_mode(MovementMode::Spectator);
+        run(&mut player, &chunk_map, &Vector3f::new(0.0, -6.0, 0.0), 60);
+        // Passes through the floor
+        assert!(near(player.pos.y, -3.5));
+        assert_eq!(player.velocity, Vector3f::new(0.0, -6.0, 0.0));
+
+        // Switching modes stops the player, and walking makes it fall again
+        player.set_mode(player.mode().next());
+        assert_eq!(player.mode(), MovementMode::Walk);
+        assert_eq!(player.velocity, Vector3f::zeros());
+        run(&mut player, &chunk_map, &Vector3f::zeros(), 1);
+        assert!(player.velocity.y < 0.0);
+    }
+
+    #[test]
+    fn test_speeds() {
+        let speeds = MovementSpeeds::default();
+        assert_eq!(speeds.speed(MovementMode::Walk, false), WALK_SPEED);
+        for &mode in &[
+            MovementMode::Walk,
+            MovementMode::Fly,
+            MovementMode::Spectator,
+        ] {
+            assert!(speeds.speed(mode, true) > speeds.speed(mode, false));
+            assert_ne!(mode.next(), mode);
+        }
+    }
+
+    #[test]
+    fn test_parse_speeds() {
+        let speeds = MovementSpeeds::parse(
+            "(fly: (normal: 8.0, sprint: 16.0), spectator: (normal: 1.0, sprint: 2.0))",
+        )
+        .unwrap();
+        assert_eq!(speeds.speed(MovementMode::Fly, false), 8.0);
+        assert_eq!(speeds.speed(MovementMode::Spectator, true), 2.0);
+        // Unlisted modes keep their defaults
+        assert_eq!(speeds.walk, MovementSpeeds::default().walk);
+
+        match MovementSpeeds::parse("(walk: (normal: 1.0))") {
+            Err(MovementSpeedsError::Parse(_)) => (),
+            result => panic!("{:?}", result),
+        }
+        assert_eq!(
+            MovementSpeeds::load("assets/nonexistent.ron").unwrap(),
+            MovementSpeeds::default()
+        );
+        assert_eq!(
+            MovementSpeeds::load(MOVEMENT_SPEEDS_PATH).unwrap(),
+            MovementSpeeds::default()
+        );
+    }
+}
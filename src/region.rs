@@ -0,0 +1,456 @@
+use crate::{
+    block::{BlockRegistry, BlockType},
+    chunk::Chunk,
+    chunk_map::{ChunkCoords, ChunkMap},
+};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use failure_derive::Fail;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, prelude::*, Cursor},
+    path::PathBuf,
+};
+
+/// Bumped whenever the layout of region files changes.
+pub const FORMAT_VERSION: u16 = 1;
+/// A region holds a cube of `REGION_SIDE_LEN`^3 chunks.
+pub const REGION_SIDE_LEN: i32 = 8;
+const REGION_LEN: usize = (REGION_SIDE_LEN * REGION_SIDE_LEN * REGION_SIDE_LEN) as usize;
+const MAGIC: &[u8; 4] = b"MCRG";
+/// Magic, version, then an (offset, length) pair per chunk
+const HEADER_LEN: usize = 4 + 2 + REGION_LEN * 8;
+
+/// Integer coordinates of a region. Region `(0, 0, 0)` holds chunks `(0, 0, 0)` through
+/// `(REGION_SIDE_LEN - 1, REGION_SIDE_LEN - 1, REGION_SIDE_LEN - 1)`.
+pub type RegionCoords = (i32, i32, i32);
+
+#[derive(Fail, Debug)]
+pub enum RegionError {
+    #[fail(display = "{}", _0)]
+    Io(#[cause] io::Error),
+    #[fail(display = "not a region file")]
+    BadMagic,
+    #[fail(
+        display = "region file has format version {}, expected {}",
+        found, expected
+    )]
+    VersionMismatch { expected: u16, found: u16 },
+    #[fail(display = "region file is corrupt: {}", _0)]
+    Corrupt(String),
+    #[fail(
+        display = "saved chunk has side length {}, expected {}",
+        found, expected
+    )]
+    SideLenMismatch { expected: usize, found: usize },
+    #[fail(display = "saved chunk contains unknown block id {}", _0)]
+    UnknownBlock(u16),
+}
+
+impl From<io::Error> for RegionError {
+    fn from(err: io::Error) -> RegionError {
+        RegionError::Io(err)
+    }
+}
+
+pub type RegionResult<T> = Result<T, RegionError>;
+
+/// Returns the coordinates of the region containing the chunk at `coords`.
+pub fn region_coords(coords: ChunkCoords) -> RegionCoords {
+    (
+        coords.0.div_euclid(REGION_SIDE_LEN),
+        coords.1.div_euclid(REGION_SIDE_LEN),
+        coords.2.div_euclid(REGION_SIDE_LEN),
+    )
+}
+
+/// Index of the chunk at `coords` in its region's offset table.
+fn region_index(coords: ChunkCoords) -> usize {
+    let f = |c: i32| c.rem_euclid(REGION_SIDE_LEN) as usize;
+    let side_len = REGION_SIDE_LEN as usize;
+    f(coords.0) + f(coords.1) * side_len + f(coords.2) * side_len * side_len
+}
+
+/// The saved chunks of one region.
+///
+/// On disk, a region is a header (magic, `FORMAT_VERSION` and a table with the offset and length of
+/// each chunk's payload), followed by the payloads. A payload is the zlib-compressed chunk side
+/// length and block ids, all big-endian `u16`s. Block id 0 means no block.
+#[derive(Debug, Clone)]
+pub struct Region {
+    /// Compressed payload of each chunk, indexed by `region_index()`
+    payloads: Vec<Option<Vec<u8>>>,
+}
+
+impl Default for Region {
+    fn default() -> Region {
+        Region::new()
+    }
+}
+
+impl Region {
+    pub fn new() -> Region {
+        Region {
+            payloads: vec![None; REGION_LEN],
+        }
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> RegionResult<Region> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        if bytes.len() < 6 || &bytes[..4] != MAGIC {
+            return Err(RegionError::BadMagic);
+        }
+        let mut cursor = Cursor::new(&bytes[4..]);
+        let version = cursor.read_u16::<BigEndian>()?;
+        if version != FORMAT_VERSION {
+            return Err(RegionError::VersionMismatch {
+                expected: FORMAT_VERSION,
+                found: version,
+            });
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err(RegionError::Corrupt("truncated header".to_owned()));
+        }
+
+        let mut payloads = Vec::with_capacity(REGION_LEN);
+        for _ in 0..REGION_LEN {
+            let offset = cursor.read_u32::<BigEndian>()? as usize;
+            let len = cursor.read_u32::<BigEndian>()? as usize;
+            if len == 0 {
+                payloads.push(None);
+            } else if offset < HEADER_LEN || offset + len > bytes.len() {
+                return Err(RegionError::Corrupt(format!(
+                    "chunk payload at {}..{} is out of bounds",
+                    offset,
+                    offset + len
+                )));
+            } else {
+                payloads.push(Some(bytes[offset..offset + len].to_vec()));
+            }
+        }
+        Ok(Region { payloads })
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> RegionResult<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_u16::<BigEndian>(FORMAT_VERSION)?;
+        let mut offset = HEADER_LEN;
+        for payload in &self.payloads {
+            let len = payload.as_ref().map_or(0, Vec::len);
+            writer.write_u32::<BigEndian>(if len > 0 { offset as u32 } else { 0 })?;
+            writer.write_u32::<BigEndian>(len as u32)?;
+            offset += len;
+        }
+        for payload in self.payloads.iter().flatten() {
+            writer.write_all(payload)?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if the chunk at `coords` has been saved to this region.
+    pub fn contains(&self, coords: ChunkCoords) -> bool {
+        self.payloads[region_index(coords)].is_some()
+    }
+
+    /// Returns the number of saved chunks.
+    pub fn len(&self) -> usize {
+        self.payloads
+            .iter()
+            .filter(|payload| payload.is_some())
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Saves `chunk` as the chunk at `coords`, replacing any previously saved chunk.
+    pub fn save_chunk(&mut self, coords: ChunkCoords, chunk: &Chunk) -> RegionResult<()> {
+        let half = (chunk.side_len() / 2) as i32;
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_u16::<BigEndian>(chunk.side_len() as u16)?;
+        for z in -half..=half {
+            for y in -half..=half {
+                for x in -half..=half {
+                    let id = chunk[(x, y, z)].map_or(0, BlockType::id);
+                    encoder.write_u16::<BigEndian>(id)?;
+                }
+            }
+        }
+        self.payloads[region_index(coords)] = Some(encoder.finish()?);
+        Ok(())
+    }
+
+    /// Replaces the blocks of `chunk` with those of the saved chunk at `coords`. Returns false and
+    /// leaves `chunk` untouched if no chunk was saved there.
+    pub fn load_chunk(&self, coords: ChunkCoords, chunk: &mut Chunk) -> RegionResult<bool> {
+        let payload = match &self.payloads[region_index(coords)] {
+            Some(payload) => payload,
+            None => return Ok(false),
+        };
+        let mut decoder = ZlibDecoder::new(payload.as_slice());
+        let side_len = decoder.read_u16::<BigEndian>()? as usize;
+        if side_len != chunk.side_len() {
+            return Err(RegionError::SideLenMismatch {
+                expected: chunk.side_len(),
+                found: side_len,
+            });
+        }
+
+        let registry = BlockRegistry::global();
+        let mut blocks = vec![None; side_len * side_len * side_len];
+        for block in &mut blocks {
+            let id = decoder.read_u16::<BigEndian>()?;
+            if id != 0 {
+                let block_type = BlockType::new(id);
+                if !registry.contains(block_type) {
+                    return Err(RegionError::UnknownBlock(id));
+                }
+                *block = Some(block_type);
+            }
+        }
+
+        let half = (side_len / 2) as i32;
+        let mut blocks = blocks.into_iter();
+        for z in -half..=half {
+            for y in -half..=half {
+                for x in -half..=half {
+                    chunk.set((x, y, z), blocks.next().unwrap());
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Saves and loads chunks to and from region files in a directory.
+#[derive(Debug, Clone)]
+pub struct RegionStore {
+    dir: PathBuf,
+}
+
+impl RegionStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> RegionStore {
+        RegionStore { dir: dir.into() }
+    }
+
+    fn region_path(&self, (x, y, z): RegionCoords) -> PathBuf {
+        self.dir.join(format!("r.{}.{}.{}.region", x, y, z))
+    }
+
+    /// Reads the region at `coords`. A region that hasn't been written yet is empty.
+    pub fn read_region(&self, coords: RegionCoords) -> RegionResult<Region> {
+        match fs::File::open(self.region_path(coords)) {
+            Ok(file) => Region::read(io::BufReader::new(file)),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(Region::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Writes `region` to a temporary file first, so that a failed write doesn't corrupt the
+    /// existing region file.
+    pub fn write_region(&self, coords: RegionCoords, region: &Region) -> RegionResult<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.region_path(coords);
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = io::BufWriter::new(fs::File::create(&tmp_path)?);
+        region.write(&mut writer)?;
+        writer.flush()?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Saves the given chunks, rewriting each affected region file once.
+    pub fn save_chunks<'a, I>(&self, chunks: I) -> RegionResult<()>
+    where
+        I: IntoIterator<Item = (ChunkCoords, &'a Chunk)>,
+    {
+        let mut by_region: HashMap<RegionCoords, Vec<(ChunkCoords, &Chunk)>> = HashMap::new();
+        for (coords, chunk) in chunks {
+            by_region
+                .entry(region_coords(coords))
+                .or_default()
+                .push((coords, chunk));
+        }
+        for (region_coords, chunks) in by_region {
+            let mut region = self.read_region(region_coords)?;
+            for (coords, chunk) in chunks {
+                region.save_chunk(coords, chunk)?;
+            }
+            self.write_region(region_coords, &region)?;
+        }
+        Ok(())
+    }
+
+    /// Saves every chunk loaded in `chunk_map`.
+    pub fn save_all(&self, chunk_map: &ChunkMap) -> RegionResult<()> {
+        self.save_chunks(chunk_map.chunks().map(|(&coords, chunk)| (coords, chunk)))
+    }
+
+    /// Loads the saved chunk at `coords` into `chunk_map`. Returns false if it was never saved.
+    pub fn load(&self, chunk_map: &mut ChunkMap, coords: ChunkCoords) -> RegionResult<bool> {
+        let region = self.read_region(region_coords(coords))?;
+        if !region.contains(coords) {
+            return Ok(false);
+        }
+        let center = chunk_map.chunk_center(coords);
+        let mut chunk = Chunk::new(center, chunk_map.chunk_side_len());
+        region.load_chunk(coords, &mut chunk)?;
+        chunk_map.insert_chunk(coords, chunk);
+        Ok(true)
+    }
+
+    /// Unloads the chunk at `coords` from `chunk_map` and writes it back to its region file.
+    /// Returns false if it wasn't loaded.
+    pub fn unload(&self, chunk_map: &mut ChunkMap, coords: ChunkCoords) -> RegionResult<bool> {
+        match chunk_map.unload_chunk(coords) {
+            Some(chunk) => {
+                self.save_chunks(vec![(coords, &chunk)])?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::CHUNK_SIDE_LEN, types::prelude::*};
+
+    fn round_trip(chunk: &Chunk, coords: ChunkCoords) -> Chunk {
+        let mut region = Region::new();
+        region.save_chunk(coords, chunk).unwrap();
+        let mut bytes = vec![];
+        region.write(&mut bytes).unwrap();
+
+        let region = Region::read(bytes.as_slice()).unwrap();
+        assert_eq!(region.len(), 1);
+        assert!(region.contains(coords));
+        let mut loaded = Chunk::new(chunk.center(), chunk.side_len());
+        assert!(region.load_chunk(coords, &mut loaded).unwrap());
+        loaded
+    }
+
+    #[test]
+    fn test_round_trip_empty() {
+        let chunk = Chunk::new(Point3f::origin(), CHUNK_SIDE_LEN);
+        let loaded = round_trip(&chunk, (0, 0, 0));
+        assert!(loaded.is_empty());
+        assert_eq!(loaded, chunk);
+    }
+
+    #[test]
+    fn test_round_trip_full() {
+        let mut chunk = Chunk::new(Point3f::new(-17.0, 0.0, 34.0), CHUNK_SIDE_LEN);
+        let half = (CHUNK_SIDE_LEN / 2) as i32;
+        let stone = BlockType::named("stone");
+        for x in -half..=half {
+            for y in -half..=half {
+                for z in -half..=half {
+                    chunk.set((x, y, z), Some(stone));
+                }
+            }
+        }
+        assert_eq!(round_trip(&chunk, (-1, 0, 2)), chunk);
+    }
+
+    #[test]
+    fn test_round_trip_mixed() {
+        let mut chunk = Chunk::new(Point3f::origin(), CHUNK_SIDE_LEN);
+        let blocks = [
+            None,
+            Some(BlockType::named("dirt")),
+            Some(BlockType::named("grass")),
+            Some(BlockType::named("cobblestone")),
+        ];
+        let half = (CHUNK_SIDE_LEN / 2) as i32;
+        for x in -half..=half {
+            for y in -half..=half {
+                for z in -half..=half {
+                    chunk.set((x, y, z), blocks[((x * 7 + y * 3 + z) as usize) % 4]);
+                }
+            }
+        }
+        let loaded = round_trip(&chunk, (3, -5, 7));
+        assert_eq!(loaded, chunk);
+        assert_eq!(loaded[(1, 0, 0)], blocks[3]);
+    }
+
+    #[test]
+    fn test_region_index() {
+        assert_eq!(region_coords((0, 0, 0)), (0, 0, 0));
+        assert_eq!(region_coords((-1, 8, 7)), (-1, 1, 0));
+        assert_eq!(region_index((0, 0, 0)), 0);
+        assert_eq!(region_index((-1, 8, 0)), 7);
+
+        // Chunks in the same region don't overwrite each other
+        let mut region = Region::new();
+        let chunk = Chunk::new(Point3f::origin(), 3);
+        region.save_chunk((0, 0, 0), &chunk).unwrap();
+        region.save_chunk((1, 0, 0), &chunk).unwrap();
+        region.save_chunk((8, 0, 0), &chunk).unwrap();
+        assert_eq!(region.len(), 2);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut bytes = vec![];
+        Region::new().write(&mut bytes).unwrap();
+        bytes[5] += 1;
+        match Region::read(bytes.as_slice()) {
+            Err(RegionError::VersionMismatch { expected, found }) => {
+                assert_eq!(expected, FORMAT_VERSION);
+                assert_eq!(found, FORMAT_VERSION + 1);
+            }
+            result => panic!("{:?}", result),
+        }
+
+        match Region::read(&b"not a region"[..]) {
+            Err(RegionError::BadMagic) => (),
+            result => panic!("{:?}", result),
+        }
+
+        let mut bytes = vec![];
+        Region::new().write(&mut bytes).unwrap();
+        bytes.truncate(100);
+        match Region::read(bytes.as_slice()) {
+            Err(RegionError::Corrupt(_)) => (),
+            result => panic!("{:?}", result),
+        }
+
+        let mut region = Region::new();
+        region
+            .save_chunk((0, 0, 0), &Chunk::new(Point3f::origin(), 3))
+            .unwrap();
+        let mut chunk = Chunk::new(Point3f::origin(), 5);
+        match region.load_chunk((0, 0, 0), &mut chunk) {
+            Err(RegionError::SideLenMismatch {
+                expected: 5,
+                found: 3,
+            }) => (),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_store() {
+        let dir = std::env::temp_dir().join(format!("minecrust-region-{}", std::process::id()));
+        let store = RegionStore::new(&dir);
+        let mut chunk_map = ChunkMap::new(3);
+        let pos = Point3f::new(-3.0, 1.0, 0.0);
+        chunk_map.insert(&pos, BlockType::named("stone"));
+        let coords = chunk_map.chunk_coords(&pos);
+
+        assert!(!store.load(&mut chunk_map, (5, 5, 5)).unwrap());
+        assert!(store.unload(&mut chunk_map, coords).unwrap());
+        assert!(!store.unload(&mut chunk_map, coords).unwrap());
+        assert_eq!(chunk_map.get(&pos), None);
+
+        assert!(store.load(&mut chunk_map, coords).unwrap());
+        assert_eq!(chunk_map.get(&pos), Some(BlockType::named("stone")));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,12 +1,16 @@
 use crate::{
-    block::BlockType,
+    chunk::ChunkMesh,
+    chunk_map::ChunkCoords,
     game::GameState,
     vulkan::{error::VulkanError, Vertex3f},
 };
 use ash::vk;
 use failure_derive::Fail;
 use std::collections::HashMap;
-use winit::{EventsLoop, Window};
+
+mod headless;
+
+pub use self::headless::{FrameRecord, HeadlessRenderer};
 
 #[derive(Fail, Debug)]
 pub enum RendererError {
@@ -33,15 +37,30 @@ pub trait Renderer {
         &mut self,
         state: &GameState,
         render_data: &RenderData,
-        resized: bool,
+        resized: Option<(u32, u32)>,
     ) -> RendererResult<()>;
-    fn events_loop(&mut self) -> &mut EventsLoop;
-    fn window(&self) -> &Window;
+}
+
+impl<R: Renderer + ?Sized> Renderer for Box<R> {
+    fn draw_frame(
+        &mut self,
+        state: &GameState,
+        render_data: &RenderData,
+        resized: Option<(u32, u32)>,
+    ) -> RendererResult<()> {
+        (**self).draw_frame(state, render_data, resized)
+    }
 }
 
 pub struct RenderData {
-    /// Block type -> Vec of vertices for that block type. This is reinitialized each frame.
-    pub vertices: HashMap<BlockType, Vec<Vertex3f>>,
+    /// Vertices of geometry outside of chunks that's in the view frustum. This is reinitialized
+    /// each frame.
+    pub vertices: Vec<Vertex3f>,
+    /// Meshes of chunks that changed since the last frame. `None` means the chunk was unloaded.
+    /// Chunks outside the view frustum aren't remeshed until they come into view.
+    pub chunk_meshes: HashMap<ChunkCoords, Option<ChunkMesh>>,
+    /// Chunks in the view frustum, sorted. Only these are drawn.
+    pub visible_chunks: Vec<ChunkCoords>,
     pub selection_vertices: Option<Vec<Vertex3f>>,
     pub fps: f32,
 }
@@ -0,0 +1,68 @@
+use crate::{
+    game::GameState,
+    renderer::{RenderData, Renderer, RendererResult},
+    types::prelude::*,
+};
+use std::{cell::RefCell, rc::Rc};
+
+/// What a `HeadlessRenderer` was asked to draw in one frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameRecord {
+    pub camera_pos: Point3f,
+    pub vertex_count: usize,
+    /// Number of chunk meshes that were updated
+    pub chunk_mesh_count: usize,
+    /// Number of chunks that were unloaded
+    pub unloaded_chunk_count: usize,
+    /// Number of chunks in the view frustum
+    pub visible_chunk_count: usize,
+    pub highlighted: Option<Point3f>,
+    /// New size of the window, if it was resized
+    pub resized: Option<(u32, u32)>,
+}
+
+/// A renderer without a window or GPU that records each frame instead of drawing it.
+#[derive(Debug, Clone, Default)]
+pub struct HeadlessRenderer {
+    frames: Rc<RefCell<Vec<FrameRecord>>>,
+}
+
+impl HeadlessRenderer {
+    pub fn new() -> HeadlessRenderer {
+        HeadlessRenderer::default()
+    }
+
+    /// Returns the frames drawn so far. The list is shared with clones of this renderer, so it can
+    /// still be read after the renderer is given to a `Game`.
+    pub fn frames(&self) -> Rc<RefCell<Vec<FrameRecord>>> {
+        self.frames.clone()
+    }
+}
+
+impl Renderer for HeadlessRenderer {
+    fn draw_frame(
+        &mut self,
+        state: &GameState,
+        render_data: &RenderData,
+        resized: Option<(u32, u32)>,
+    ) -> RendererResult<()> {
+        let (chunk_mesh_count, unloaded_chunk_count) =
+            render_data
+                .chunk_meshes
+                .values()
+                .fold((0, 0), |(meshes, unloaded), mesh| match mesh {
+                    Some(_) => (meshes + 1, unloaded),
+                    None => (meshes, unloaded + 1),
+                });
+        self.frames.borrow_mut().push(FrameRecord {
+            camera_pos: state.camera.pos,
+            vertex_count: render_data.vertices.len(),
+            chunk_mesh_count,
+            unloaded_chunk_count,
+            visible_chunk_count: render_data.visible_chunks.len(),
+            highlighted: state.highlighted,
+            resized,
+        });
+        Ok(())
+    }
+}
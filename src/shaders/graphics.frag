@@ -2,12 +2,13 @@
 #extension GL_ARB_separate_shader_objects : enable
 
 layout (location = 0) in vec2 fragTexCoord;
+layout (location = 1) flat in uint fragLayer;
 
 layout (location = 0) out vec4 outColor;
 
 layout (binding = 1) uniform sampler texSampler;
-layout (binding = 2) uniform texture2D tex;
+layout (binding = 2) uniform texture2DArray tex;
 
 void main() {
-    outColor = texture(sampler2D(tex, texSampler), fragTexCoord);
+    outColor = texture(sampler2DArray(tex, texSampler), vec3(fragTexCoord, fragLayer));
 }
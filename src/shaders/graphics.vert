@@ -3,8 +3,10 @@
 
 layout (location = 0) in vec3 inPosition;
 layout (location = 1) in vec2 inTexCoord;
+layout (location = 2) in uint inLayer;
 
 layout (location = 0) out vec2 fragTexCoord;
+layout (location = 1) flat out uint fragLayer;
 
 layout (push_constant) uniform PushConsts {
     mat4 proj_view;
@@ -13,4 +15,5 @@ layout (push_constant) uniform PushConsts {
 void main() {
     gl_Position = pushConsts.proj_view * vec4(inPosition, 1.0);
     fragTexCoord = inTexCoord;
+    fragLayer = inLayer;
 }
@@ -39,3 +39,56 @@ pub enum Face {
     Back = 4,
     Bottom = 5,
 }
+
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::Top,
+        Face::Front,
+        Face::Left,
+        Face::Right,
+        Face::Back,
+        Face::Bottom,
+    ];
+
+    /// Outward-facing unit normal of the face.
+    pub fn normal(self) -> Vector3f {
+        match self {
+            Face::Top => Vector3f::y(),
+            Face::Bottom => -Vector3f::y(),
+            Face::Front => Vector3f::z(),
+            Face::Back => -Vector3f::z(),
+            Face::Left => -Vector3f::x(),
+            Face::Right => Vector3f::x(),
+        }
+    }
+
+    /// `normal()` as an integer offset.
+    pub fn offset(self) -> (i32, i32, i32) {
+        let n = self.normal();
+        (n.x as i32, n.y as i32, n.z as i32)
+    }
+
+    /// Directions along which texture coordinates u and v increase on the face. Textures on the
+    /// side faces are upright when viewed from outside a cube.
+    pub fn uv_axes(self) -> (Vector3f, Vector3f) {
+        match self {
+            Face::Top => (Vector3f::x(), Vector3f::z()),
+            Face::Bottom => (Vector3f::x(), -Vector3f::z()),
+            Face::Front => (Vector3f::x(), -Vector3f::y()),
+            Face::Back => (-Vector3f::x(), -Vector3f::y()),
+            Face::Left => (Vector3f::z(), -Vector3f::y()),
+            Face::Right => (-Vector3f::z(), -Vector3f::y()),
+        }
+    }
+
+    pub fn opposite(self) -> Face {
+        match self {
+            Face::Top => Face::Bottom,
+            Face::Bottom => Face::Top,
+            Face::Front => Face::Back,
+            Face::Back => Face::Front,
+            Face::Left => Face::Right,
+            Face::Right => Face::Left,
+        }
+    }
+}
@@ -1,10 +1,15 @@
 use crate::types::prelude::*;
 
 pub trait Point2fExt {
+    fn almost_eq(&self, p: &Point2f) -> bool;
     fn almost_is_int(&self) -> bool;
 }
 
 impl Point2fExt for Point2f {
+    fn almost_eq(&self, p: &Point2f) -> bool {
+        self.x.almost_eq(p.x) && self.y.almost_eq(p.y)
+    }
+
     fn almost_is_int(&self) -> bool {
         self.x.almost_is_int() && self.y.almost_is_int()
     }
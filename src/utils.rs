@@ -1,6 +1,7 @@
 pub mod f32;
 pub mod point3f;
 pub mod quaternion4f;
+pub mod transform3f;
 
 pub const NSEC_PER_SEC: u32 = 1_000_000_000;
 
@@ -22,7 +23,7 @@ pub fn max<T: PartialOrd>(a: T, b: T) -> T {
 
 pub fn clamp<T: PartialOrd>(min: T, max: T, t: T) -> T {
     assert!(max >= min);
-    self::min(min, self::max(max, t))
+    self::max(min, self::min(max, t))
 }
 
 #[macro_export]
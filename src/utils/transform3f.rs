@@ -0,0 +1,13 @@
+use crate::types::prelude::*;
+
+/// Interpolates each entry of the transforms' matrices. Exact for translations and scaling, and
+/// close enough for small rotations such as those between two ticks.
+pub fn clerp(a: &Transform3f, b: &Transform3f, t: f32) -> Transform3f {
+    if t <= 0.0 {
+        *a
+    } else if t >= 1.0 {
+        *b
+    } else {
+        Transform3f::from_matrix_unchecked(a.matrix() * (1.0 - t) + b.matrix() * t)
+    }
+}
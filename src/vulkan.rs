@@ -1,8 +1,10 @@
 pub mod app;
 mod buffer;
+mod chunk_layout;
 mod descriptor;
 pub mod error;
 mod one_time_command_buffer;
+mod range_allocator;
 mod text;
 mod texture;
 mod vertex;
@@ -11,10 +13,11 @@ mod vertex_input;
 pub use self::{app::VulkanApp, vertex::Vertex3f};
 
 use self::error::{from_vk_result, VulkanError, VulkanResult};
+use crate::platform::WindowHandle;
 use ash::{
     extensions::{
         ext::DebugUtils,
-        khr::{Surface, Swapchain, Win32Surface},
+        khr::{Surface, Swapchain},
     },
     prelude::VkResult,
     version::{DeviceV1_0, EntryV1_0, InstanceV1_0},
@@ -25,7 +28,6 @@ use std::{
     os::raw::{c_char, c_void},
     rc::Rc,
 };
-use winit::dpi::LogicalSize;
 
 pub struct VulkanCore {
     entry: ash::Entry,
@@ -41,36 +43,30 @@ pub struct VulkanCore {
     pub surface: Surface,
     pub surface_handle: vk::SurfaceKHR,
 
-    pub events_loop: winit::EventsLoop,
-    pub window: winit::Window,
-
-    debug_messenger: vk::DebugUtilsMessengerEXT,
+    /// `None` if `VK_EXT_debug_utils` isn't available
+    debug_messenger: Option<vk::DebugUtilsMessengerEXT>,
 }
 
 impl VulkanCore {
-    pub fn new(name: &str, screen_width: u32, screen_height: u32) -> VulkanResult<VulkanCore> {
-        let events_loop = winit::EventsLoop::new();
-        let window = winit::WindowBuilder::new()
-            .with_title("Minecrust")
-            .with_resizable(true)
-            .with_dimensions(LogicalSize::new(
-                f64::from(screen_width),
-                f64::from(screen_height),
-            ))
-            .build(&events_loop)
-            .unwrap();
+    /// The window behind `window` must outlive the returned core.
+    pub fn new(window: WindowHandle, name: &str) -> VulkanResult<VulkanCore> {
         let app_name = CString::new(name)?;
         let entry = Entry::new().unwrap();
-        let layer_names = [CString::new("VK_LAYER_LUNARG_standard_validation")?];
+        let layer_names = validation_layers(&entry)?;
         let layer_names_raw: Vec<*const c_char> = layer_names
             .iter()
             .map(|raw_name| raw_name.as_ptr())
             .collect();
-        let extension_names_raw = vec![
+        let debug_utils = entry.enumerate_instance_extension_properties()?.iter().any(
+            |ext| unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) } == DebugUtils::name(),
+        );
+        let mut extension_names_raw = vec![
             Surface::name().as_ptr(),
-            Win32Surface::name().as_ptr(),
-            DebugUtils::name().as_ptr(),
+            surface_extension_name(window).as_ptr(),
         ];
+        if debug_utils {
+            extension_names_raw.push(DebugUtils::name().as_ptr());
+        }
         let app_info = vk::ApplicationInfo::builder()
             .application_name(&app_name)
             .application_version(0)
@@ -87,24 +83,29 @@ impl VulkanCore {
             let instance = entry
                 .create_instance(&instance_ci, None)
                 .expect("Instance creation error");
-            let debug_messenger_create_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
-                .message_severity(
-                    vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
-                        | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-                        | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
-                )
-                .message_type(vk::DebugUtilsMessageTypeFlagsEXT::all())
-                .pfn_user_callback(Some(vulkan_debug_callback))
-                .build();
-            let mut debug_messenger: vk::DebugUtilsMessengerEXT = Default::default();
-            create_debug_utils_messenger_ext(
-                &entry,
-                instance.handle(),
-                &debug_messenger_create_info,
-                std::ptr::null(),
-                &mut debug_messenger,
-            )?;
-            let surface_handle = create_surface(&entry, &instance, &window)?;
+            let debug_messenger = if debug_utils {
+                let debug_messenger_create_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
+                    .message_severity(
+                        vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
+                            | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+                            | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+                    )
+                    .message_type(vk::DebugUtilsMessageTypeFlagsEXT::all())
+                    .pfn_user_callback(Some(vulkan_debug_callback))
+                    .build();
+                let mut debug_messenger: vk::DebugUtilsMessengerEXT = Default::default();
+                create_debug_utils_messenger_ext(
+                    &entry,
+                    instance.handle(),
+                    &debug_messenger_create_info,
+                    std::ptr::null(),
+                    &mut debug_messenger,
+                )?;
+                Some(debug_messenger)
+            } else {
+                None
+            };
+            let surface_handle = create_surface(&entry, &instance, window)?;
             let surface = Surface::new(&entry, &instance);
             let (physical_device, graphics_queue_family_index, transfer_queue_family_index) = {
                 if let (
@@ -164,8 +165,6 @@ impl VulkanCore {
 
                 surface,
                 surface_handle,
-                events_loop,
-                window,
 
                 debug_messenger,
             })
@@ -224,13 +223,15 @@ impl Drop for VulkanCore {
         unsafe {
             self.device.destroy_device(None);
             self.surface.destroy_surface(self.surface_handle, None);
-            destroy_debug_utils_messenger_ext(
-                &self.entry,
-                self.instance.handle(),
-                self.debug_messenger,
-                std::ptr::null(),
-            )
-            .unwrap();
+            if let Some(debug_messenger) = self.debug_messenger {
+                destroy_debug_utils_messenger_ext(
+                    &self.entry,
+                    self.instance.handle(),
+                    debug_messenger,
+                    std::ptr::null(),
+                )
+                .unwrap();
+            }
             self.instance.destroy_instance(None);
         }
     }
@@ -278,27 +279,80 @@ unsafe fn destroy_debug_utils_messenger_ext(
     }
 }
 
-#[cfg(target_os = "windows")]
+/// The validation layers to enable: the first available of the Khronos layer and the older LunarG
+/// one, or none. Software drivers such as lavapipe often run without either installed.
+fn validation_layers(entry: &Entry) -> VulkanResult<Vec<CString>> {
+    let available = entry.enumerate_instance_layer_properties()?;
+    for &name in &[
+        "VK_LAYER_KHRONOS_validation",
+        "VK_LAYER_LUNARG_standard_validation",
+    ] {
+        let name = CString::new(name)?;
+        if available
+            .iter()
+            .any(|layer| unsafe { CStr::from_ptr(layer.layer_name.as_ptr()) } == name.as_c_str())
+        {
+            return Ok(vec![name]);
+        }
+    }
+    Ok(vec![])
+}
+
+/// The instance extension needed to create a surface for `window`.
+fn surface_extension_name(window: WindowHandle) -> &'static CStr {
+    match window {
+        #[cfg(target_os = "windows")]
+        WindowHandle::Win32 { .. } => ash::extensions::khr::Win32Surface::name(),
+        #[cfg(all(unix, not(target_os = "macos")))]
+        WindowHandle::Xlib { .. } => ash::extensions::khr::XlibSurface::name(),
+        #[cfg(all(unix, not(target_os = "macos")))]
+        WindowHandle::Wayland { .. } => ash::extensions::khr::WaylandSurface::name(),
+    }
+}
+
 unsafe fn create_surface<E: EntryV1_0, I: InstanceV1_0>(
     entry: &E,
     instance: &I,
-    window: &winit::Window,
+    window: WindowHandle,
 ) -> Result<vk::SurfaceKHR, vk::Result> {
-    use winapi::shared::windef::HWND;
-    use winapi::um::libloaderapi::GetModuleHandleW;
-    use winit::os::windows::WindowExt;
-
-    let hwnd = window.get_hwnd() as HWND;
-    let hinstance = GetModuleHandleW(std::ptr::null()) as *const c_void;
-    let win32_create_info = vk::Win32SurfaceCreateInfoKHR {
-        s_type: vk::StructureType::WIN32_SURFACE_CREATE_INFO_KHR,
-        p_next: std::ptr::null(),
-        flags: Default::default(),
-        hinstance,
-        hwnd: hwnd as *const c_void,
-    };
-    let win32_surface = Win32Surface::new(entry, instance);
-    win32_surface.create_win32_surface(&win32_create_info, None)
+    match window {
+        #[cfg(target_os = "windows")]
+        WindowHandle::Win32 { hinstance, hwnd } => {
+            let create_info = vk::Win32SurfaceCreateInfoKHR {
+                s_type: vk::StructureType::WIN32_SURFACE_CREATE_INFO_KHR,
+                p_next: std::ptr::null(),
+                flags: Default::default(),
+                hinstance,
+                hwnd,
+            };
+            ash::extensions::khr::Win32Surface::new(entry, instance)
+                .create_win32_surface(&create_info, None)
+        }
+        #[cfg(all(unix, not(target_os = "macos")))]
+        WindowHandle::Xlib { display, window } => {
+            let create_info = vk::XlibSurfaceCreateInfoKHR {
+                s_type: vk::StructureType::XLIB_SURFACE_CREATE_INFO_KHR,
+                p_next: std::ptr::null(),
+                flags: Default::default(),
+                dpy: display as *mut _,
+                window: window as vk::Window,
+            };
+            ash::extensions::khr::XlibSurface::new(entry, instance)
+                .create_xlib_surface(&create_info, None)
+        }
+        #[cfg(all(unix, not(target_os = "macos")))]
+        WindowHandle::Wayland { display, surface } => {
+            let create_info = vk::WaylandSurfaceCreateInfoKHR {
+                s_type: vk::StructureType::WAYLAND_SURFACE_CREATE_INFO_KHR,
+                p_next: std::ptr::null(),
+                flags: Default::default(),
+                display: display as *mut _,
+                surface: surface as *mut _,
+            };
+            ash::extensions::khr::WaylandSurface::new(entry, instance)
+                .create_wayland_surface(&create_info, None)
+        }
+    }
 }
 
 /// Returns (physical_device, graphics queue, transfer queue)
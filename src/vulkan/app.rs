@@ -1,12 +1,15 @@
 use crate::{
-    block::{BlockType, NUM_BLOCK_TYPES},
+    atlas::TextureAtlas,
+    block::BlockRegistry,
+    camera::Camera,
     game::GameState,
-    na::geometry::Perspective3,
+    platform::WindowHandle,
     renderer::{RenderData, Renderer, RendererResult},
     types::{prelude::*, Color},
     utils::clamp,
     vulkan::{
         buffer::Buffer,
+        chunk_layout::ChunkLayout,
         descriptor::{DescriptorSetLayout, DescriptorSetLayoutBinding},
         error::{VulkanError, VulkanResult},
         one_time_command_buffer::OneTimeCommandBuffer,
@@ -25,12 +28,20 @@ use ash::{
 use byteorder::ByteOrder;
 use byteorder::LittleEndian;
 use image;
-use std::{collections::HashMap, ffi::CString, fs::File, io::prelude::*, path::Path};
-use winit::{dpi::LogicalSize, EventsLoop, Window};
+use std::{collections::HashMap, ffi::CString, path::Path};
+
+/// The SPIR-V that build.rs compiled `name`, e.g. `graphics-vert`, to
+macro_rules! include_spv {
+    ($name:expr) => {
+        include_bytes!(concat!(env!("OUT_DIR"), "/", $name, ".spv"))
+    };
+}
 
 // Pin to swapchain len for now
 const MAX_FRAMES_IN_FLIGHT: usize = 3;
 const VERTEX_BUFFER_CAPCITY: vk::DeviceSize = 1 << 20;
+/// The meshes of all loaded chunks share one vertex buffer, each in its own range
+const CHUNK_VERTEX_BUFFER_CAPACITY: vk::DeviceSize = 1 << 25;
 const N_GLYPH_TEXTURES: usize = 256;
 const CROSSHAIR_WIDTH: f32 = 32.0;
 const CROSSHAIR_HEIGHT: f32 = 32.0;
@@ -205,11 +216,15 @@ pub struct VulkanApp {
     graphics_pipeline: vk::Pipeline,
     graphics_pipeline_layout: vk::PipelineLayout,
 
-    graphics_staging_vertex_buffers: HashMap<BlockType, Vec<Buffer<Vertex3f>>>,
-    graphics_vertex_buffers: HashMap<BlockType, Vec<Buffer<Vertex3f>>>,
+    graphics_staging_vertex_buffers: Vec<Buffer<Vertex3f>>,
+    graphics_vertex_buffers: Vec<Buffer<Vertex3f>>,
+
+    chunk_layout: ChunkLayout,
+    chunk_staging_vertex_buffers: Vec<Buffer<Vertex3f>>,
+    chunk_vertex_buffers: Vec<Buffer<Vertex3f>>,
     graphics_draw_cmd_bufs: Vec<vk::CommandBuffer>,
 
-    graphics_descriptor_sets: HashMap<BlockType, Vec<vk::DescriptorSet>>,
+    graphics_descriptor_sets: Vec<vk::DescriptorSet>,
     graphics_descriptor_set_layout: DescriptorSetLayout,
 
     graphics_texture_sampler: vk::Sampler,
@@ -282,25 +297,21 @@ pub struct VulkanApp {
 }
 
 impl VulkanApp {
-    pub fn new(screen_width: u32, screen_height: u32) -> VulkanResult<VulkanApp> {
+    /// The window behind `window` must outlive the app.
+    pub fn new(
+        window: WindowHandle,
+        screen_width: u32,
+        screen_height: u32,
+    ) -> VulkanResult<VulkanApp> {
         unsafe {
-            let core = VulkanCore::new("Minecrust", screen_width, screen_height)?;
+            let core = VulkanCore::new(window, "Minecrust")?;
 
             let view_mat = Matrix4f::look_at_rh(
                 &Point3f::new(5.0, 1.0, 5.0),
                 &Point3f::origin(),
                 &Vector3f::y_axis(),
             );
-            let mut flip_mat = Matrix4f::from_diagonal(&Vector4f::new(1.0, -1.0, 0.5, 1.0));
-            flip_mat[(2, 3)] = 0.5;
-            let proj_mat = flip_mat
-                * Perspective3::new(
-                    screen_width as f32 / screen_height as f32,
-                    f32::to_radians(45.0),
-                    0.1,
-                    100.0,
-                )
-                .to_homogeneous();
+            let proj_mat = Camera::projection(screen_width as f32 / screen_height as f32);
 
             let mut screen_space_normalize_mat = Matrix3f::new_nonuniform_scaling(&Vector2f::new(
                 1.0 / (screen_width as f32 / 2.0),
@@ -401,6 +412,12 @@ impl VulkanApp {
 
                 graphics_staging_vertex_buffers: Default::default(),
                 graphics_vertex_buffers: Default::default(),
+
+                chunk_layout: ChunkLayout::new(
+                    CHUNK_VERTEX_BUFFER_CAPACITY as usize / std::mem::size_of::<Vertex3f>(),
+                ),
+                chunk_staging_vertex_buffers: Default::default(),
+                chunk_vertex_buffers: Default::default(),
                 graphics_draw_cmd_bufs: Default::default(),
 
                 graphics_descriptor_sets: Default::default(),
@@ -514,8 +531,13 @@ impl VulkanApp {
                 } else {
                     (vec![0], (1, 1))
                 };
-                let texture =
-                    base.new_texture_image_from_bytes(&pad_font_bytes(buffer), dimensions, 1)?;
+                let texture = base.new_texture_image_from_bytes(
+                    &pad_font_bytes(buffer),
+                    dimensions,
+                    1,
+                    vk::ImageViewType::TYPE_2D,
+                    1,
+                )?;
 
                 base.glyph_textures.push(texture);
                 base.glyph_metrics.push(glyph.metrics().into());
@@ -525,14 +547,9 @@ impl VulkanApp {
                 "texture".to_owned(),
                 base.create_texture_image("assets/texture.jpg")?,
             );
-            base.textures.insert(
-                BlockType::Cobblestone.to_string(),
-                base.create_texture_image("assets/cobblestone-border-arrow.png")?,
-            );
-            base.textures.insert(
-                BlockType::Sandstone.to_string(),
-                base.create_texture_image("assets/sandstone.png")?,
-            );
+            let atlas = TextureAtlas::load(BlockRegistry::global().textures())?;
+            base.textures
+                .insert("blocks".to_owned(), base.create_texture_array(&atlas)?);
             base.textures.insert(
                 "crosshair".to_owned(),
                 base.create_texture_image("assets/crosshair.png")?,
@@ -676,6 +693,8 @@ impl VulkanApp {
             self.swapchain_image_views.push(self.create_image_view(
                 swapchain_image,
                 self.surface_format.format,
+                vk::ImageViewType::TYPE_2D,
+                1,
                 1,
                 vk::ImageAspectFlags::COLOR,
             )?);
@@ -688,12 +707,12 @@ impl VulkanApp {
 
     fn create_descriptor_pool(&mut self) -> VkResult<()> {
         let mut bindings = HashMap::new();
-        let mut layouts = vec![
+        let layouts = vec![
             &self.text_descriptor_set_layout,
             &self.crosshair_descriptor_set_layout,
             &self.selection_descriptor_set_layout,
+            &self.graphics_descriptor_set_layout,
         ];
-        layouts.extend(&[&self.graphics_descriptor_set_layout; NUM_BLOCK_TYPES]);
         for layout in &layouts {
             for binding in &layout.bindings {
                 *bindings.entry(binding.ty).or_insert(0) += 1;
@@ -936,8 +955,8 @@ impl VulkanApp {
 
     unsafe fn create_graphics_pipeline(&mut self) -> VulkanResult<()> {
         let (pipeline_layout, pipeline) = self.create_pipeline::<Vertex3f>(
-            self.create_shader_module_from_file("src/shaders/graphics-vert.spv")?,
-            self.create_shader_module_from_file("src/shaders/graphics-frag.spv")?,
+            self.create_shader_module(include_spv!("graphics-vert"))?,
+            self.create_shader_module(include_spv!("graphics-frag"))?,
             vk::PipelineColorBlendAttachmentState::builder()
                 .color_write_mask(vk::ColorComponentFlags::all())
                 .blend_enable(false)
@@ -965,8 +984,8 @@ impl VulkanApp {
 
     unsafe fn create_text_pipeline(&mut self) -> VulkanResult<()> {
         let (pipeline_layout, pipeline) = self.create_pipeline::<TextVertex>(
-            self.create_shader_module_from_file("src/shaders/text-vert.spv")?,
-            self.create_shader_module_from_file("src/shaders/text-frag.spv")?,
+            self.create_shader_module(include_spv!("text-vert"))?,
+            self.create_shader_module(include_spv!("text-frag"))?,
             vk::PipelineColorBlendAttachmentState::builder()
                 .color_write_mask(vk::ColorComponentFlags::all())
                 .blend_enable(true)
@@ -994,8 +1013,8 @@ impl VulkanApp {
 
     unsafe fn create_crosshair_pipeline(&mut self) -> VulkanResult<()> {
         let (pipeline_layout, pipeline) = self.create_pipeline::<Vertex2f>(
-            self.create_shader_module_from_file("src/shaders/crosshair-vert.spv")?,
-            self.create_shader_module_from_file("src/shaders/crosshair-frag.spv")?,
+            self.create_shader_module(include_spv!("crosshair-vert"))?,
+            self.create_shader_module(include_spv!("crosshair-frag"))?,
             vk::PipelineColorBlendAttachmentState::builder()
                 .color_write_mask(vk::ColorComponentFlags::all())
                 .blend_enable(true)
@@ -1023,8 +1042,8 @@ impl VulkanApp {
 
     unsafe fn create_selection_pipeline(&mut self) -> VulkanResult<()> {
         let (pipeline_layout, pipeline) = self.create_pipeline::<Vertex3f>(
-            self.create_shader_module_from_file("src/shaders/selection-vert.spv")?,
-            self.create_shader_module_from_file("src/shaders/selection-frag.spv")?,
+            self.create_shader_module(include_spv!("selection-vert"))?,
+            self.create_shader_module(include_spv!("selection-frag"))?,
             vk::PipelineColorBlendAttachmentState::builder()
                 .color_write_mask(vk::ColorComponentFlags::all())
                 .blend_enable(true)
@@ -1153,6 +1172,7 @@ impl VulkanApp {
         &mut self,
         index: usize,
         selection_active: bool,
+        upload_chunks: bool,
     ) -> VkResult<vk::CommandBuffer> {
         let command_buffer = self.take_ownership_cmd_buffers[index];
         let begin_info = vk::CommandBufferBeginInfo::builder().build();
@@ -1168,15 +1188,23 @@ impl VulkanApp {
                 .dst_queue_family_index(self.core.graphics_queue_family_index)
                 .buffer(self.text_vertex_buffers[index].buffer())
                 .build()];
-            // FIXME: Only transfer vertices for necessary blocks
-            for graphics_vertex_buffers in self.graphics_vertex_buffers.values() {
+            buffer_memory_buffers.push(
+                vk::BufferMemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::empty())
+                    .dst_access_mask(vk::AccessFlags::VERTEX_ATTRIBUTE_READ)
+                    .src_queue_family_index(self.core.transfer_queue_family_index)
+                    .dst_queue_family_index(self.core.graphics_queue_family_index)
+                    .buffer(self.graphics_vertex_buffers[index].buffer())
+                    .build(),
+            );
+            if upload_chunks {
                 buffer_memory_buffers.push(
                     vk::BufferMemoryBarrier::builder()
                         .src_access_mask(vk::AccessFlags::empty())
                         .dst_access_mask(vk::AccessFlags::VERTEX_ATTRIBUTE_READ)
                         .src_queue_family_index(self.core.transfer_queue_family_index)
                         .dst_queue_family_index(self.core.graphics_queue_family_index)
-                        .buffer(graphics_vertex_buffers[index].buffer())
+                        .buffer(self.chunk_vertex_buffers[index].buffer())
                         .build(),
                 );
             }
@@ -1264,6 +1292,7 @@ impl VulkanApp {
         &mut self,
         index: usize,
         selection_active: bool,
+        chunk_copies: &[vk::BufferCopy],
     ) -> VkResult<vk::CommandBuffer> {
         let cmd_buf = self.transfer_cmd_bufs[index];
         let begin_info = vk::CommandBufferBeginInfo::builder()
@@ -1274,18 +1303,26 @@ impl VulkanApp {
                 .device
                 .begin_command_buffer(cmd_buf, &begin_info)?;
 
-            // FIXME: Only copy necessary buffers
-            for (block_type, staging_vertex_buffers) in &self.graphics_staging_vertex_buffers {
+            if self.graphics_staging_vertex_buffers[index].buf_len > 0 {
                 self.core.device.cmd_copy_buffer(
                     cmd_buf,
-                    staging_vertex_buffers[index].buffer(),
-                    self.graphics_vertex_buffers[block_type][index].buffer(),
+                    self.graphics_staging_vertex_buffers[index].buffer(),
+                    self.graphics_vertex_buffers[index].buffer(),
                     &[vk::BufferCopy::builder()
-                        .size(staging_vertex_buffers[index].buf_len)
+                        .size(self.graphics_staging_vertex_buffers[index].buf_len)
                         .build()],
                 );
             }
 
+            if !chunk_copies.is_empty() {
+                self.core.device.cmd_copy_buffer(
+                    cmd_buf,
+                    self.chunk_staging_vertex_buffers[index].buffer(),
+                    self.chunk_vertex_buffers[index].buffer(),
+                    chunk_copies,
+                );
+            }
+
             self.core.device.cmd_copy_buffer(
                 cmd_buf,
                 self.text_staging_vertex_buffers[index].buffer(),
@@ -1313,14 +1350,23 @@ impl VulkanApp {
                 .dst_queue_family_index(self.core.graphics_queue_family_index)
                 .buffer(self.text_vertex_buffers[index].buffer())
                 .build()];
-            for graphics_vertex_buffers in self.graphics_vertex_buffers.values() {
+            buffer_memory_buffers.push(
+                vk::BufferMemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::empty())
+                    .src_queue_family_index(self.core.transfer_queue_family_index)
+                    .dst_queue_family_index(self.core.graphics_queue_family_index)
+                    .buffer(self.graphics_vertex_buffers[index].buffer())
+                    .build(),
+            );
+            if !chunk_copies.is_empty() {
                 buffer_memory_buffers.push(
                     vk::BufferMemoryBarrier::builder()
                         .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
                         .dst_access_mask(vk::AccessFlags::empty())
                         .src_queue_family_index(self.core.transfer_queue_family_index)
                         .dst_queue_family_index(self.core.graphics_queue_family_index)
-                        .buffer(graphics_vertex_buffers[index].buffer())
+                        .buffer(self.chunk_vertex_buffers[index].buffer())
                         .build(),
                 );
             }
@@ -1351,6 +1397,25 @@ impl VulkanApp {
         Ok(cmd_buf)
     }
 
+    /// Copies the chunk meshes missing from the chunk vertex buffer for swapchain image `index`
+    /// into its staging buffer, at the same offsets. Returns the regions to copy over.
+    fn stage_chunk_uploads(&mut self, index: usize) -> Vec<vk::BufferCopy> {
+        let vertex_size = std::mem::size_of::<Vertex3f>() as vk::DeviceSize;
+        let mut copies = vec![];
+        for (range, mesh) in self.chunk_layout.take_pending(index) {
+            self.chunk_staging_vertex_buffers[index].copy_data_at(range.start, mesh);
+            let offset = range.start as vk::DeviceSize * vertex_size;
+            copies.push(
+                vk::BufferCopy::builder()
+                    .src_offset(offset)
+                    .dst_offset(offset)
+                    .size(range.len() as vk::DeviceSize * vertex_size)
+                    .build(),
+            );
+        }
+        copies
+    }
+
     fn update_crosshair_vtx_buf(&mut self, screen_width: u32, screen_height: u32) -> VkResult<()> {
         let center_x = screen_width as f32 / 2.0;
         let center_y = screen_height as f32 / 2.0;
@@ -1472,31 +1537,43 @@ impl VulkanApp {
                 vk::PipelineBindPoint::GRAPHICS,
                 self.graphics_pipeline,
             );
-            // FIXME: Don't draw all possible block types
-            for (block_type, graphics_vertex_buffers) in &self.graphics_vertex_buffers {
-                let graphics_staging_vertex_buffer =
-                    &self.graphics_staging_vertex_buffers[block_type][index];
-                if graphics_staging_vertex_buffer.len > 0 {
-                    self.core.device.cmd_bind_vertex_buffers(
-                        cmd_buf,
-                        0,
-                        &[graphics_vertex_buffers[index].buffer()],
-                        &[0],
-                    );
-                    self.core.device.cmd_bind_descriptor_sets(
-                        cmd_buf,
-                        vk::PipelineBindPoint::GRAPHICS,
-                        self.graphics_pipeline_layout,
-                        0,
-                        &[self.graphics_descriptor_sets[block_type][index]],
-                        &[],
-                    );
+            self.core.device.cmd_bind_descriptor_sets(
+                cmd_buf,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.graphics_pipeline_layout,
+                0,
+                &[self.graphics_descriptor_sets[index]],
+                &[],
+            );
+            if self.graphics_staging_vertex_buffers[index].len > 0 {
+                self.core.device.cmd_bind_vertex_buffers(
+                    cmd_buf,
+                    0,
+                    &[self.graphics_vertex_buffers[index].buffer()],
+                    &[0],
+                );
+                self.core.device.cmd_draw(
+                    cmd_buf,
+                    self.graphics_staging_vertex_buffers[index].len as u32,
+                    1,
+                    0,
+                    0,
+                );
+            }
 
+            if !self.chunk_layout.is_empty() {
+                self.core.device.cmd_bind_vertex_buffers(
+                    cmd_buf,
+                    0,
+                    &[self.chunk_vertex_buffers[index].buffer()],
+                    &[0],
+                );
+                for range in self.chunk_layout.visible_ranges() {
                     self.core.device.cmd_draw(
                         cmd_buf,
-                        graphics_staging_vertex_buffer.len as u32,
+                        range.len() as u32,
                         1,
-                        0,
+                        range.start as u32,
                         0,
                     );
                 }
@@ -1639,15 +1716,18 @@ impl VulkanApp {
         Ok(cmd_buf)
     }
 
-    /// Returns (staging buffer, vertex buffer).
-    fn create_buffers_for_block_type(&mut self, block_type: BlockType) -> VkResult<()> {
+    /// Returns (staging buffers, vertex buffers), one of each per swapchain image.
+    fn create_vertex_buffers(
+        &self,
+        capacity: vk::DeviceSize,
+    ) -> VkResult<(Vec<Buffer<Vertex3f>>, Vec<Buffer<Vertex3f>>)> {
         let mut staging_bufs = vec![];
         let mut vertex_bufs = vec![];
 
         for _ in 0..self.swapchain_len {
             let mut staging_buf = Buffer::new_init(
                 &self.core,
-                VERTEX_BUFFER_CAPCITY,
+                capacity,
                 vk::BufferUsageFlags::TRANSFER_SRC,
                 vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
             )?;
@@ -1655,7 +1735,7 @@ impl VulkanApp {
 
             let vertex_buf = Buffer::new_init(
                 &self.core,
-                VERTEX_BUFFER_CAPCITY,
+                capacity,
                 vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::VERTEX_BUFFER,
                 vk::MemoryPropertyFlags::DEVICE_LOCAL,
             )?;
@@ -1663,16 +1743,19 @@ impl VulkanApp {
             vertex_bufs.push(vertex_buf);
         }
 
-        self.graphics_staging_vertex_buffers
-            .insert(block_type, staging_bufs);
-        self.graphics_vertex_buffers.insert(block_type, vertex_bufs);
-        Ok(())
+        Ok((staging_bufs, vertex_bufs))
     }
 
     unsafe fn create_buffers(&mut self) -> VkResult<()> {
         // graphics
-        self.create_buffers_for_block_type(BlockType::Cobblestone)?;
-        self.create_buffers_for_block_type(BlockType::Sandstone)?;
+        let (staging_bufs, vertex_bufs) = self.create_vertex_buffers(VERTEX_BUFFER_CAPCITY)?;
+        self.graphics_staging_vertex_buffers = staging_bufs;
+        self.graphics_vertex_buffers = vertex_bufs;
+        let (staging_bufs, vertex_bufs) =
+            self.create_vertex_buffers(CHUNK_VERTEX_BUFFER_CAPACITY)?;
+        self.chunk_staging_vertex_buffers = staging_bufs;
+        self.chunk_vertex_buffers = vertex_bufs;
+        self.chunk_layout.reset_buffers(self.swapchain_len);
 
         for _ in 0..self.swapchain_len {
             // text
@@ -1856,13 +1939,16 @@ impl VulkanApp {
         unsafe {
             self.core.device.device_wait_idle()?;
             self.clean_up_swapchain();
-            let LogicalSize {
+            let (screen_width, screen_height) = (self.screen_width, self.screen_height);
+            self.create_swapchain(screen_width, screen_height)?;
+            // The stored size may be stale, e.g. if the swapchain went out of date before the
+            // resize event arrived, so go by the size the surface asked for
+            let vk::Extent2D {
                 width: screen_width,
                 height: screen_height,
-            } = self.core.window.get_inner_size().unwrap();
-            self.screen_width = screen_width as u32;
-            self.screen_height = screen_height as u32;
-            self.create_swapchain(self.screen_width, self.screen_height)?;
+            } = self.swapchain_extent;
+            self.screen_width = screen_width;
+            self.screen_height = screen_height;
 
             self.create_render_pass()?;
             self.create_graphics_pipeline()?;
@@ -1873,16 +1959,7 @@ impl VulkanApp {
             self.create_depth_resources()?;
             self.create_framebuffers()?;
 
-            let mut flip_mat = Matrix4f::from_diagonal(&Vector4f::new(1.0, -1.0, 0.5, 1.0));
-            flip_mat[(2, 3)] = 0.5;
-            self.proj_mat = flip_mat
-                * Perspective3::new(
-                    screen_width as f32 / screen_height as f32,
-                    f32::to_radians(45.0),
-                    0.1,
-                    100.0,
-                )
-                .to_homogeneous();
+            self.proj_mat = Camera::projection(screen_width as f32 / screen_height as f32);
 
             let mut screen_space_normalize_mat = Matrix3f::new_nonuniform_scaling(&Vector2f::new(
                 1.0 / (screen_width as f32 / 2.0),
@@ -1912,6 +1989,7 @@ impl VulkanApp {
         width: u32,
         height: u32,
         mip_levels: u32,
+        array_layers: u32,
         num_samples: vk::SampleCountFlags,
         format: vk::Format,
         tiling: vk::ImageTiling,
@@ -1926,7 +2004,7 @@ impl VulkanApp {
                 depth: 1,
             })
             .mip_levels(mip_levels)
-            .array_layers(1)
+            .array_layers(array_layers)
             .format(format)
             .tiling(tiling)
             .initial_layout(vk::ImageLayout::UNDEFINED)
@@ -1953,8 +2031,10 @@ impl VulkanApp {
         bytes: &[u8],
         (img_width, img_height): (u32, u32),
         mip_levels: u32,
+        view_type: vk::ImageViewType,
+        layer_count: u32,
     ) -> VulkanResult<Texture> {
-        let img_size = vk::DeviceSize::from(img_width * img_height * 4);
+        let img_size = vk::DeviceSize::from(img_width * img_height * 4 * layer_count);
         let (staging_buffer, staging_buffer_memory) = self.core.create_buffer(
             img_size,
             vk::BufferUsageFlags::TRANSFER_SRC,
@@ -1975,6 +2055,7 @@ impl VulkanApp {
             img_width,
             img_height,
             mip_levels,
+            layer_count,
             vk::SampleCountFlags::TYPE_1,
             vk::Format::R8G8B8A8_UNORM,
             vk::ImageTiling::OPTIMAL,
@@ -1994,6 +2075,7 @@ impl VulkanApp {
             texture_image,
             vk::Format::R8G8B8A8_UNORM,
             mip_levels,
+            layer_count,
             vk::ImageLayout::UNDEFINED,
             vk::ImageLayout::TRANSFER_DST_OPTIMAL,
         )?;
@@ -2003,6 +2085,7 @@ impl VulkanApp {
             texture_image,
             img_width,
             img_height,
+            layer_count,
         )?;
         command_buffer.submit(&[])?;
 
@@ -2012,12 +2095,14 @@ impl VulkanApp {
             img_width,
             img_height,
             mip_levels,
+            layer_count,
         )?;
 
         self.core.device.destroy_buffer(staging_buffer, None);
         self.core.device.free_memory(staging_buffer_memory, None);
 
-        let texture_image_view = self.create_texture_image_view(texture_image, mip_levels)?;
+        let texture_image_view =
+            self.create_texture_image_view(texture_image, view_type, mip_levels, layer_count)?;
 
         Ok(Texture::new(
             &self.core,
@@ -2032,19 +2117,38 @@ impl VulkanApp {
         let (img_width, img_height) = img.dimensions();
         let mip_levels = f32::floor(f32::log2(u32::max(img_width, img_height) as f32)) as u32 + 1;
 
-        self.new_texture_image_from_bytes(&img.to_vec(), (img_width, img_height), mip_levels)
+        self.new_texture_image_from_bytes(
+            &img.to_vec(),
+            (img_width, img_height),
+            mip_levels,
+            vk::ImageViewType::TYPE_2D,
+            1,
+        )
+    }
+
+    /// Creates an array texture with one layer per texture in `atlas`.
+    unsafe fn create_texture_array(&self, atlas: &TextureAtlas) -> VulkanResult<Texture> {
+        self.new_texture_image_from_bytes(
+            &atlas.bytes(),
+            atlas.dimensions(),
+            atlas.mip_levels(),
+            vk::ImageViewType::TYPE_2D_ARRAY,
+            atlas.len() as u32,
+        )
     }
 
     unsafe fn create_image_view(
         &self,
         image: vk::Image,
         format: vk::Format,
+        view_type: vk::ImageViewType,
         mip_levels: u32,
+        layer_count: u32,
         aspect_flags: vk::ImageAspectFlags,
     ) -> VulkanResult<vk::ImageView> {
         let image_view_ci = vk::ImageViewCreateInfo::builder()
             .image(image)
-            .view_type(vk::ImageViewType::TYPE_2D)
+            .view_type(view_type)
             .format(format)
             .subresource_range(
                 vk::ImageSubresourceRange::builder()
@@ -2052,7 +2156,7 @@ impl VulkanApp {
                     .base_mip_level(0)
                     .level_count(mip_levels)
                     .base_array_layer(0)
-                    .layer_count(1)
+                    .layer_count(layer_count)
                     .build(),
             )
             .build();
@@ -2066,6 +2170,7 @@ impl VulkanApp {
         tex_width: u32,
         tex_height: u32,
         mip_levels: u32,
+        layer_count: u32,
     ) -> VulkanResult<()> {
         let format_properties = self
             .core
@@ -2095,7 +2200,7 @@ impl VulkanApp {
                 vk::ImageSubresourceRange::builder()
                     .aspect_mask(vk::ImageAspectFlags::COLOR)
                     .base_array_layer(0)
-                    .layer_count(1)
+                    .layer_count(layer_count)
                     .level_count(1)
                     .build(),
             )
@@ -2133,7 +2238,7 @@ impl VulkanApp {
                         .aspect_mask(vk::ImageAspectFlags::COLOR)
                         .mip_level(i - 1)
                         .base_array_layer(0)
-                        .layer_count(1)
+                        .layer_count(layer_count)
                         .build(),
                 )
                 .dst_offsets([
@@ -2149,7 +2254,7 @@ impl VulkanApp {
                         .aspect_mask(vk::ImageAspectFlags::COLOR)
                         .mip_level(i)
                         .base_array_layer(0)
-                        .layer_count(1)
+                        .layer_count(layer_count)
                         .build(),
                 )
                 .build();
@@ -2207,12 +2312,16 @@ impl VulkanApp {
     unsafe fn create_texture_image_view(
         &self,
         texture_image: vk::Image,
+        view_type: vk::ImageViewType,
         mip_levels: u32,
+        layer_count: u32,
     ) -> VulkanResult<vk::ImageView> {
         self.create_image_view(
             texture_image,
             vk::Format::R8G8B8A8_UNORM,
+            view_type,
             mip_levels,
+            layer_count,
             vk::ImageAspectFlags::COLOR,
         )
     }
@@ -2223,6 +2332,7 @@ impl VulkanApp {
         image: vk::Image,
         format: vk::Format,
         mip_levels: u32,
+        layer_count: u32,
         old_layout: vk::ImageLayout,
         new_layout: vk::ImageLayout,
     ) -> VulkanResult<()> {
@@ -2249,7 +2359,7 @@ impl VulkanApp {
                 .base_mip_level(0)
                 .level_count(mip_levels)
                 .base_array_layer(0)
-                .layer_count(1)
+                .layer_count(layer_count)
                 .build(),
         );
 
@@ -2317,6 +2427,7 @@ impl VulkanApp {
         image: vk::Image,
         width: u32,
         height: u32,
+        layer_count: u32,
     ) -> VulkanResult<()> {
         self.core.device.cmd_copy_buffer_to_image(
             command_buffer.command_buffer,
@@ -2332,7 +2443,7 @@ impl VulkanApp {
                         .aspect_mask(vk::ImageAspectFlags::COLOR)
                         .mip_level(0)
                         .base_array_layer(0)
-                        .layer_count(1)
+                        .layer_count(layer_count)
                         .build(),
                 )
                 .image_offset(vk::Offset3D::default())
@@ -2376,16 +2487,6 @@ impl VulkanApp {
         Ok(())
     }
 
-    unsafe fn create_shader_module_from_file<P: AsRef<Path>>(
-        &self,
-        file: P,
-    ) -> VulkanResult<vk::ShaderModule> {
-        let mut shader_spv_file = File::open(file)?;
-        let mut buf = vec![];
-        shader_spv_file.read_to_end(&mut buf)?;
-        self.create_shader_module(&buf)
-    }
-
     unsafe fn create_shader_module(&self, code: &[u8]) -> VulkanResult<vk::ShaderModule> {
         let mut code_u32: Vec<u32> = vec![0; code.len() / 4];
         LittleEndian::read_u32_into(code, &mut code_u32);
@@ -2444,7 +2545,7 @@ impl VulkanApp {
         Ok(())
     }
 
-    fn create_descriptor_sets_for_block_type(&mut self, block_type: BlockType) -> VkResult<()> {
+    fn create_graphics_descriptor_sets(&mut self) -> VkResult<()> {
         let layouts = vec![self.graphics_descriptor_set_layout.layout(); self.swapchain_len];
         let descriptor_set_alloc_info = vk::DescriptorSetAllocateInfo::builder()
             .descriptor_pool(self.descriptor_pool)
@@ -2471,7 +2572,7 @@ impl VulkanApp {
                     .build();
 
                 // binding 2: image
-                let texture = &self.textures[&block_type.to_string()];
+                let texture = &self.textures["blocks"];
                 let texture_descriptor_image_info = vk::DescriptorImageInfo::builder()
                     .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
                     .image_view(texture.view)
@@ -2490,17 +2591,14 @@ impl VulkanApp {
                     &[],
                 );
             }
-            self.graphics_descriptor_sets
-                .insert(block_type, graphics_descriptor_sets);
+            self.graphics_descriptor_sets = graphics_descriptor_sets;
         }
 
         Ok(())
     }
 
     unsafe fn create_descriptor_sets(&mut self) -> VkResult<()> {
-        // FIXME
-        self.create_descriptor_sets_for_block_type(BlockType::Cobblestone)?;
-        self.create_descriptor_sets_for_block_type(BlockType::Sandstone)?;
+        self.create_graphics_descriptor_sets()?;
 
         let layouts = vec![self.text_descriptor_set_layout.layout(); self.swapchain_len];
         self.text_descriptor_sets = self.core.device.allocate_descriptor_sets(
@@ -2646,6 +2744,7 @@ impl VulkanApp {
             self.swapchain_extent.width,
             self.swapchain_extent.height,
             1,
+            1,
             self.msaa_samples,
             color_format,
             vk::ImageTiling::OPTIMAL,
@@ -2657,6 +2756,8 @@ impl VulkanApp {
         self.color_image_view = self.create_image_view(
             self.color_image,
             color_format,
+            vk::ImageViewType::TYPE_2D,
+            1,
             1,
             vk::ImageAspectFlags::COLOR,
         )?;
@@ -2672,6 +2773,7 @@ impl VulkanApp {
             self.color_image,
             color_format,
             1,
+            1,
             vk::ImageLayout::UNDEFINED,
             vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
         )?;
@@ -2688,14 +2790,21 @@ impl VulkanApp {
             self.swapchain_extent.width,
             self.swapchain_extent.height,
             1,
+            1,
             self.msaa_samples,
             depth_format,
             vk::ImageTiling::OPTIMAL,
             vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
             vk::MemoryPropertyFlags::DEVICE_LOCAL,
         )?;
-        self.depth_image_view =
-            self.create_image_view(depth_image, depth_format, 1, vk::ImageAspectFlags::DEPTH)?;
+        self.depth_image_view = self.create_image_view(
+            depth_image,
+            depth_format,
+            vk::ImageViewType::TYPE_2D,
+            1,
+            1,
+            vk::ImageAspectFlags::DEPTH,
+        )?;
         self.depth_image = depth_image;
         self.depth_image_memory = depth_image_memory;
         let command_buffer = OneTimeCommandBuffer::new(
@@ -2708,6 +2817,7 @@ impl VulkanApp {
             self.depth_image,
             depth_format,
             1,
+            1,
             vk::ImageLayout::UNDEFINED,
             vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
         )?;
@@ -2851,15 +2961,17 @@ impl Drop for VulkanApp {
                 buf.deinit();
             }
 
-            for bufs in self.graphics_staging_vertex_buffers.values_mut() {
-                for buf in bufs {
-                    buf.deinit();
-                }
+            for buf in &mut self.graphics_staging_vertex_buffers {
+                buf.deinit();
             }
-            for bufs in self.graphics_vertex_buffers.values_mut() {
-                for buf in bufs {
-                    buf.deinit();
-                }
+            for buf in &mut self.graphics_vertex_buffers {
+                buf.deinit();
+            }
+            for buf in &mut self.chunk_staging_vertex_buffers {
+                buf.deinit();
+            }
+            for buf in &mut self.chunk_vertex_buffers {
+                buf.deinit();
             }
 
             self.crosshair_staging_vertex_buffer.deinit();
@@ -2901,10 +3013,12 @@ impl Renderer for VulkanApp {
         game_state: &GameState,
         RenderData {
             vertices,
+            chunk_meshes,
+            visible_chunks,
             fps,
             selection_vertices,
         }: &RenderData,
-        resized: bool,
+        resized: Option<(u32, u32)>,
     ) -> RendererResult<()> {
         unsafe {
             self.core.device.wait_for_fences(
@@ -2913,12 +3027,18 @@ impl Renderer for VulkanApp {
                 std::u64::MAX,
             )?;
 
+            // Before anything that can skip drawing this frame, since `chunk_meshes` won't be sent
+            // again
+            self.chunk_layout.update(chunk_meshes, visible_chunks)?;
+
             self.view_mat = game_state.camera.to_matrix();
             self.uniform_push_constants = UniformPushConstants {
                 proj_view: self.proj_mat * self.view_mat,
             };
 
-            if resized {
+            if let Some((screen_width, screen_height)) = resized {
+                self.screen_width = screen_width;
+                self.screen_height = screen_height;
                 self.recreate_swapchain()?;
                 println!("recreated swapchain");
                 return Ok(());
@@ -2947,13 +3067,10 @@ impl Renderer for VulkanApp {
                     )?;
 
                     // graphics
-                    for (block_type, block_vertices) in vertices {
-                        // This only copies vertices for necessary block types
-                        self.graphics_staging_vertex_buffers
-                            .get_mut(block_type)
-                            .unwrap()[image_index]
-                            .copy_data(block_vertices)?;
-                    }
+                    self.graphics_staging_vertex_buffers[image_index].copy_data(vertices)?;
+
+                    // chunks
+                    let chunk_copies = self.stage_chunk_uploads(image_index);
 
                     // selection
                     if let Some(vertices) = selection_vertices {
@@ -2969,8 +3086,11 @@ impl Renderer for VulkanApp {
                         Color::new(1.0, 0.0, 0.0),
                     )?;
 
-                    let transfer_cmd_buf =
-                        [self.new_transfer_cmd_buf(image_index, selection_vertices.is_some())?];
+                    let transfer_cmd_buf = [self.new_transfer_cmd_buf(
+                        image_index,
+                        selection_vertices.is_some(),
+                        &chunk_copies,
+                    )?];
                     let signal_semaphores = [self.transfer_ownership_semaphores[image_index]];
                     self.core.device.queue_submit(
                         self.core.transfer_queue,
@@ -2981,8 +3101,11 @@ impl Renderer for VulkanApp {
                         vk::Fence::null(),
                     )?;
 
-                    let command_buffers = [self
-                        .new_take_ownership_cmd_bufs(image_index, selection_vertices.is_some())?];
+                    let command_buffers = [self.new_take_ownership_cmd_bufs(
+                        image_index,
+                        selection_vertices.is_some(),
+                        !chunk_copies.is_empty(),
+                    )?];
                     let wait_semaphores = [self.transfer_ownership_semaphores[image_index]];
                     let wait_dst_stage_mask = [vk::PipelineStageFlags::VERTEX_INPUT];
                     self.core.device.queue_submit(
@@ -3073,12 +3196,4 @@ impl Renderer for VulkanApp {
             }
         }
     }
-
-    fn events_loop(&mut self) -> &mut EventsLoop {
-        &mut self.core.events_loop
-    }
-
-    fn window(&self) -> &Window {
-        &self.core.window
-    }
 }
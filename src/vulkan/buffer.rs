@@ -102,6 +102,22 @@ impl<T> Buffer<T> {
         }
     }
 
+    /// Copies all elements in `ts` into the buffer starting at element `offset`, without changing
+    /// `len`. The buffer must be host coherent.
+    pub fn copy_data_at(&mut self, offset: usize, ts: &[T]) {
+        assert!(
+            ((offset + ts.len()) * std::mem::size_of::<T>()) as vk::DeviceSize <= self.capacity
+        );
+        assert!(!self.ptr.is_null());
+        assert!(self
+            .memory_property_flags
+            .contains(vk::MemoryPropertyFlags::HOST_COHERENT));
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(ts.as_ptr(), self.ptr.add(offset), ts.len());
+        }
+    }
+
     pub fn map(&mut self) -> VkResult<*mut T> {
         assert!(self
             .memory_property_flags
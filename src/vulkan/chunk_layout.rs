@@ -0,0 +1,164 @@
+use crate::{
+    chunk::ChunkMesh,
+    chunk_map::ChunkCoords,
+    vulkan::{
+        error::{VulkanError, VulkanResult},
+        range_allocator::RangeAllocator,
+    },
+};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+/// Where each chunk's mesh lives in the chunk vertex buffers, one per swapchain image, and which
+/// of those buffers are still missing it.
+#[derive(Debug, Clone)]
+pub struct ChunkLayout {
+    /// Latest mesh of each loaded chunk
+    meshes: HashMap<ChunkCoords, ChunkMesh>,
+    /// Vertices that each chunk's mesh occupies in every buffer. Chunks with empty meshes have no
+    /// range.
+    ranges: HashMap<ChunkCoords, Range<usize>>,
+    allocator: RangeAllocator,
+    /// Chunks whose latest meshes each buffer is missing
+    pending: Vec<HashSet<ChunkCoords>>,
+    /// Chunks to draw in the current frame
+    visible: Vec<ChunkCoords>,
+}
+
+impl ChunkLayout {
+    /// `capacity` is the number of vertices each buffer holds.
+    pub fn new(capacity: usize) -> ChunkLayout {
+        ChunkLayout {
+            meshes: HashMap::new(),
+            ranges: HashMap::new(),
+            allocator: RangeAllocator::new(capacity),
+            pending: vec![],
+            visible: vec![],
+        }
+    }
+
+    /// Starts over with `buffer_count` empty buffers, e.g. after the swapchain was recreated.
+    pub fn reset_buffers(&mut self, buffer_count: usize) {
+        self.pending = vec![self.ranges.keys().cloned().collect(); buffer_count];
+    }
+
+    /// Takes in the chunk meshes that changed since the last frame and the chunks to draw. Changes
+    /// are only sent once, so this must be called for every frame, even ones that aren't drawn.
+    pub fn update(
+        &mut self,
+        chunk_meshes: &HashMap<ChunkCoords, Option<ChunkMesh>>,
+        visible_chunks: &[ChunkCoords],
+    ) -> VulkanResult<()> {
+        // Free all the old ranges first, so that they can be reused for the new meshes
+        for coords in chunk_meshes.keys() {
+            self.remove(coords);
+        }
+        for (&coords, mesh) in chunk_meshes {
+            if let Some(mesh) = mesh {
+                self.insert(coords, mesh)?;
+            }
+        }
+        // Culled chunks stay in the buffers; they just aren't drawn
+        self.visible.clear();
+        self.visible.extend_from_slice(visible_chunks);
+        Ok(())
+    }
+
+    fn remove(&mut self, coords: &ChunkCoords) {
+        if let Some(range) = self.ranges.remove(coords) {
+            self.allocator.free(range);
+        }
+        self.meshes.remove(coords);
+        for pending in &mut self.pending {
+            pending.remove(coords);
+        }
+    }
+
+    /// Adds the mesh of a chunk that has none and queues it for every buffer.
+    fn insert(&mut self, coords: ChunkCoords, mesh: &ChunkMesh) -> VulkanResult<()> {
+        if mesh.is_empty() {
+            return Ok(());
+        }
+        let range = self
+            .allocator
+            .alloc(mesh.len())
+            .ok_or_else(|| VulkanError::Str("chunk vertex buffer is full".to_owned()))?;
+        self.ranges.insert(coords, range);
+        self.meshes.insert(coords, mesh.clone());
+        for pending in &mut self.pending {
+            pending.insert(coords);
+        }
+        Ok(())
+    }
+
+    /// The meshes that buffer `index` is missing and where they go. Afterwards the buffer counts
+    /// as up to date.
+    pub fn take_pending(&mut self, index: usize) -> Vec<(Range<usize>, &ChunkMesh)> {
+        let ranges = &self.ranges;
+        let meshes = &self.meshes;
+        self.pending[index]
+            .drain()
+            .map(|coords| (ranges[&coords].clone(), &meshes[&coords]))
+            .collect()
+    }
+
+    /// Ranges of the visible chunks that have meshes
+    pub fn visible_ranges<'a>(&'a self) -> impl Iterator<Item = &'a Range<usize>> + 'a {
+        self.visible
+            .iter()
+            .filter_map(move |coords| self.ranges.get(coords))
+    }
+
+    /// Whether no chunk has a non-empty mesh
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{types::prelude::*, vulkan::Vertex3f};
+
+    fn mesh(len: usize) -> ChunkMesh {
+        vec![Vertex3f::new(Point3f::origin(), Point2f::origin()); len]
+    }
+
+    #[test]
+    fn test_update_without_drawing() {
+        let mut layout = ChunkLayout::new(10);
+        layout.reset_buffers(2);
+        let changed = [((0, 0, 0), Some(mesh(4))), ((1, 0, 0), Some(mesh(4)))];
+        layout
+            .update(&changed.iter().cloned().collect(), &[(0, 0, 0)])
+            .unwrap();
+        assert_eq!(layout.take_pending(0).len(), 2);
+        let lens: Vec<usize> = layout.visible_ranges().map(|range| range.len()).collect();
+        assert_eq!(lens, vec![4]);
+
+        // A frame that isn't drawn, e.g. because the window was resized, still takes in the
+        // changes
+        let changed = [((0, 0, 0), Some(mesh(6))), ((1, 0, 0), None)];
+        layout
+            .update(&changed.iter().cloned().collect(), &[(0, 0, 0)])
+            .unwrap();
+        layout.update(&HashMap::new(), &[(0, 0, 0)]).unwrap();
+        for index in 0..2 {
+            let pending = layout.take_pending(index);
+            assert_eq!(pending.len(), 1);
+            assert_eq!(pending[0].1.len(), 6);
+        }
+        // The unloaded chunk's range was freed, so the new mesh fits
+        assert_eq!(
+            layout.visible_ranges().cloned().collect::<Vec<_>>(),
+            vec![0..6]
+        );
+        assert!(layout.take_pending(0).is_empty());
+
+        // New buffers need every mesh again
+        layout.reset_buffers(1);
+        assert_eq!(layout.take_pending(0).len(), 1);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::atlas::AtlasError;
 use ash::vk;
 use failure_derive::Fail;
 use image;
@@ -18,6 +19,8 @@ pub enum VulkanError {
     VkError(#[cause] vk::Result),
     #[fail(display = "{}", _0)]
     FtError(#[cause] freetype::Error),
+    #[fail(display = "{}", _0)]
+    AtlasError(#[cause] AtlasError),
 }
 
 impl From<image::ImageError> for VulkanError {
@@ -50,6 +53,12 @@ impl From<freetype::Error> for VulkanError {
     }
 }
 
+impl From<AtlasError> for VulkanError {
+    fn from(err: AtlasError) -> VulkanError {
+        VulkanError::AtlasError(err)
+    }
+}
+
 pub type VulkanResult<T> = Result<T, VulkanError>;
 
 pub fn from_vk_result<T>(result: vk::Result, t: T) -> VulkanResult<T> {
@@ -0,0 +1,82 @@
+use std::ops::Range;
+
+/// Hands out non-overlapping ranges of `0..capacity`, e.g. for suballocating a buffer.
+#[derive(Debug, Clone)]
+pub struct RangeAllocator {
+    /// Sorted, non-adjacent free ranges
+    free: Vec<Range<usize>>,
+}
+
+impl RangeAllocator {
+    pub fn new(capacity: usize) -> RangeAllocator {
+        RangeAllocator {
+            free: std::iter::once(0..capacity).collect(),
+        }
+    }
+
+    /// Returns the first free range of length `len`, or `None` if there isn't one.
+    pub fn alloc(&mut self, len: usize) -> Option<Range<usize>> {
+        let i = self.free.iter().position(|range| range.len() >= len)?;
+        let start = self.free[i].start;
+        self.free[i].start += len;
+        if self.free[i].start == self.free[i].end {
+            self.free.remove(i);
+        }
+        Some(start..start + len)
+    }
+
+    /// Returns `range`, which must have been allocated, to the free ranges.
+    pub fn free(&mut self, range: Range<usize>) {
+        if range.start == range.end {
+            return;
+        }
+        let i = self
+            .free
+            .iter()
+            .position(|free| free.start >= range.end)
+            .unwrap_or(self.free.len());
+        assert!(i == 0 || self.free[i - 1].end <= range.start);
+
+        let merge_prev = i > 0 && self.free[i - 1].end == range.start;
+        let merge_next = i < self.free.len() && self.free[i].start == range.end;
+        match (merge_prev, merge_next) {
+            (true, true) => {
+                self.free[i - 1].end = self.free[i].end;
+                self.free.remove(i);
+            }
+            (true, false) => self.free[i - 1].end = range.end,
+            (false, true) => self.free[i].start = range.start,
+            (false, false) => self.free.insert(i, range),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alloc_free() {
+        let mut allocator = RangeAllocator::new(10);
+        let a = allocator.alloc(3).unwrap();
+        let b = allocator.alloc(3).unwrap();
+        let c = allocator.alloc(3).unwrap();
+        assert_eq!((a.clone(), b.clone(), c.clone()), (0..3, 3..6, 6..9));
+        assert_eq!(allocator.alloc(2), None);
+
+        // Freed ranges are reused first fit
+        allocator.free(b);
+        assert_eq!(allocator.alloc(2), Some(3..5));
+        assert_eq!(allocator.alloc(2), None);
+
+        // Neighboring free ranges merge
+        allocator.free(a);
+        allocator.free(3..5);
+        assert_eq!(allocator.alloc(6), Some(0..6));
+        allocator.free(c);
+        allocator.free(0..6);
+        assert_eq!(allocator.alloc(10), Some(0..10));
+        allocator.free(0..10);
+        assert_eq!(allocator.alloc(0), Some(0..0));
+    }
+}
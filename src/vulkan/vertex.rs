@@ -46,15 +46,24 @@ impl VertexInput for Vertex2f {
 pub struct Vertex3f {
     pub pos: Point3f,
     pub uv: Point2f,
+    /// Layer of the block texture array to sample
+    pub layer: u32,
 }
 
 impl Vertex3f {
     pub fn new(pos: Point3f, uv: Point2f) -> Vertex3f {
-        Vertex3f { pos, uv }
+        Vertex3f { pos, uv, layer: 0 }
+    }
+
+    pub fn with_layer(self, layer: u32) -> Vertex3f {
+        Vertex3f { layer, ..self }
     }
 
     pub fn transform(&self, transform: &Transform3f) -> Vertex3f {
-        Vertex3f::new(transform * self.pos, self.uv)
+        Vertex3f {
+            pos: transform * self.pos,
+            ..*self
+        }
     }
 }
 
@@ -81,6 +90,12 @@ impl VertexInput for Vertex3f {
                 .format(vk::Format::R32G32_SFLOAT)
                 .offset(offset_of!(Vertex3f, uv) as u32)
                 .build(),
+            vk::VertexInputAttributeDescription::builder()
+                .binding(0)
+                .location(2)
+                .format(vk::Format::R32_UINT)
+                .offset(offset_of!(Vertex3f, layer) as u32)
+                .build(),
         ]
     }
 }
@@ -0,0 +1,158 @@
+mod noise;
+
+pub use self::noise::Perlin;
+use crate::{
+    block::BlockType,
+    chunk::Chunk,
+    chunk_map::{ChunkCoords, ChunkMap},
+};
+
+/// Columns whose surface is at or below this height are covered in sand instead of grass.
+pub const SEA_LEVEL: i32 = 0;
+/// Surface height that the terrain varies around
+const BASE_HEIGHT: i32 = 4;
+/// Maximum distance of the surface from `BASE_HEIGHT`
+const AMPLITUDE: f32 = 12.0;
+/// Horizontal size, in blocks, of the largest terrain features
+const SCALE: f32 = 48.0;
+const OCTAVES: usize = 4;
+/// Number of dirt blocks between the surface and the stone underneath
+const DIRT_DEPTH: i32 = 3;
+
+/// Generates heightmap terrain from a seed. The same seed always generates the same chunks.
+#[derive(Debug, Clone)]
+pub struct WorldGenerator {
+    seed: u64,
+    noise: Perlin,
+    stone: BlockType,
+    dirt: BlockType,
+    grass: BlockType,
+    sand: BlockType,
+}
+
+impl WorldGenerator {
+    pub fn new(seed: u64) -> WorldGenerator {
+        WorldGenerator {
+            seed,
+            noise: Perlin::new(seed),
+            stone: BlockType::named("stone"),
+            dirt: BlockType::named("dirt"),
+            grass: BlockType::named("grass"),
+            sand: BlockType::named("sandstone"),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Returns the height of the topmost block in column `(x, z)`.
+    pub fn height(&self, x: i32, z: i32) -> i32 {
+        let n = self
+            .noise
+            .fractal(x as f32 / SCALE, z as f32 / SCALE, OCTAVES, 0.5, 2.0);
+        BASE_HEIGHT + (n * AMPLITUDE).round() as i32
+    }
+
+    /// Returns the block at height `y` in a column whose topmost block is at `height`.
+    fn block(&self, y: i32, height: i32) -> Option<BlockType> {
+        if y > height {
+            None
+        } else if y <= height - DIRT_DEPTH {
+            Some(self.stone)
+        } else if height <= SEA_LEVEL {
+            Some(self.sand)
+        } else if y == height {
+            Some(self.grass)
+        } else {
+            Some(self.dirt)
+        }
+    }
+
+    /// Fills `chunk` with terrain, based on its position.
+    pub fn generate_chunk(&self, chunk: &mut Chunk) {
+        let half = (chunk.side_len() / 2) as i32;
+        let center = chunk.center();
+        let (cx, cy, cz) = (
+            center.x.round() as i32,
+            center.y.round() as i32,
+            center.z.round() as i32,
+        );
+        for x in -half..=half {
+            for z in -half..=half {
+                let height = self.height(cx + x, cz + z);
+                for y in -half..=half {
+                    chunk.set((x, y, z), self.block(cy + y, height));
+                }
+            }
+        }
+    }
+
+    /// Loads the chunk at `coords` in `chunk_map` and fills it with terrain.
+    pub fn generate(&self, chunk_map: &mut ChunkMap, coords: ChunkCoords) {
+        self.generate_chunk(chunk_map.load_chunk(coords));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::CHUNK_SIDE_LEN, types::prelude::*};
+
+    fn generate(seed: u64, center: Point3f) -> Chunk {
+        let mut chunk = Chunk::new(center, CHUNK_SIDE_LEN);
+        WorldGenerator::new(seed).generate_chunk(&mut chunk);
+        chunk
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let center = Point3f::new(17.0, 0.0, -34.0);
+        let chunk1 = generate(1234, center);
+        let chunk2 = generate(1234, center);
+        assert!(!chunk1.is_empty());
+        assert_eq!(chunk1, chunk2);
+        assert!(chunk1.blocks().eq(chunk2.blocks()));
+
+        assert_ne!(chunk1, generate(4321, center));
+    }
+
+    #[test]
+    fn test_layers() {
+        let gen = WorldGenerator::new(5);
+        for x in -20..20 {
+            for z in -20..20 {
+                let height = gen.height(x, z);
+                assert!((height - BASE_HEIGHT).abs() <= AMPLITUDE as i32);
+                let top = gen.block(height, height);
+                if height > SEA_LEVEL {
+                    assert_eq!(top, Some(gen.grass));
+                    assert_eq!(gen.block(height - 1, height), Some(gen.dirt));
+                } else {
+                    assert_eq!(top, Some(gen.sand));
+                }
+                assert_eq!(gen.block(height + 1, height), None);
+                assert_eq!(gen.block(height - DIRT_DEPTH, height), Some(gen.stone));
+            }
+        }
+    }
+
+    #[test]
+    fn test_chunk_borders() {
+        let gen = WorldGenerator::new(99);
+        let mut chunk_map = ChunkMap::new(CHUNK_SIDE_LEN);
+        for x in -1..=1 {
+            for y in -1..=1 {
+                gen.generate(&mut chunk_map, (x, y, 0));
+            }
+        }
+        // Columns line up across chunk borders
+        let half = (CHUNK_SIDE_LEN / 2) as i32;
+        for x in -half - 3..=half + 3 {
+            let height = gen.height(x, 0);
+            let pos = Point3f::new(x as f32, height as f32, 0.0);
+            assert!(chunk_map.get(&pos).is_some());
+            assert_eq!(chunk_map.get(&(pos + Vector3f::y())), None);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+/// 2D Perlin gradient noise. The same seed always produces the same noise.
+#[derive(Debug, Clone)]
+pub struct Perlin {
+    /// A shuffled permutation of 0..256, repeated twice to avoid wrapping indices
+    perm: Vec<u8>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        let mut perm: Vec<u8> = (0..=255).collect();
+        let mut state = seed;
+        // Fisher-Yates shuffle
+        for i in (1..perm.len()).rev() {
+            let j = (splitmix64(&mut state) % (i as u64 + 1)) as usize;
+            perm.swap(i, j);
+        }
+        Perlin {
+            perm: perm.iter().chain(perm.iter()).cloned().collect(),
+        }
+    }
+
+    /// Returns the noise value at `(x, y)`, in [-1, 1]. The noise is 0 at integer coordinates.
+    pub fn get(&self, x: f32, y: f32) -> f32 {
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let xi = (x0 as i32 & 255) as usize;
+        let yi = (y0 as i32 & 255) as usize;
+        let xf = x - x0;
+        let yf = y - y0;
+
+        let hash = |dx: usize, dy: usize| self.perm[self.perm[xi + dx] as usize + yi + dy];
+        let n00 = gradient(hash(0, 0), xf, yf);
+        let n10 = gradient(hash(1, 0), xf - 1.0, yf);
+        let n01 = gradient(hash(0, 1), xf, yf - 1.0);
+        let n11 = gradient(hash(1, 1), xf - 1.0, yf - 1.0);
+
+        let u = fade(xf);
+        let v = fade(yf);
+        lerp(v, lerp(u, n00, n10), lerp(u, n01, n11))
+    }
+
+    /// Sums `octaves` layers of noise. Each layer has `lacunarity` times the frequency and
+    /// `persistence` times the amplitude of the previous one. The result is normalized to [-1, 1].
+    pub fn fractal(
+        &self,
+        x: f32,
+        y: f32,
+        octaves: usize,
+        persistence: f32,
+        lacunarity: f32,
+    ) -> f32 {
+        let mut total = 0.0;
+        let mut frequency = 1.0;
+        let mut amplitude = 1.0;
+        let mut max_amplitude = 0.0;
+        for _ in 0..octaves {
+            total += self.get(x * frequency, y * frequency) * amplitude;
+            max_amplitude += amplitude;
+            frequency *= lacunarity;
+            amplitude *= persistence;
+        }
+        total / max_amplitude
+    }
+}
+
+/// Returns the dot product of `(x, y)` with one of 8 gradients chosen by `hash`.
+fn gradient(hash: u8, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+/// Perlin's smootherstep curve, 6t^5 - 15t^4 + 10t^3.
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+    a + t * (b - a)
+}
+
+/// Advances `state` and returns the next value of a SplitMix64 sequence.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deterministic() {
+        let noise1 = Perlin::new(42);
+        let noise2 = Perlin::new(42);
+        let noise3 = Perlin::new(43);
+        assert_eq!(noise1.perm, noise2.perm);
+        assert_ne!(noise1.perm, noise3.perm);
+        for i in 0..100 {
+            let (x, y) = (i as f32 * 0.37, i as f32 * -1.13);
+            assert_eq!(noise1.get(x, y).to_bits(), noise2.get(x, y).to_bits());
+        }
+    }
+
+    #[test]
+    fn test_range() {
+        let noise = Perlin::new(7);
+        let mut min = 0.0f32;
+        let mut max = 0.0f32;
+        for i in 0..200 {
+            for j in 0..200 {
+                let (x, y) = (i as f32 * 0.173 - 17.0, j as f32 * 0.191 - 19.0);
+                let n = noise.get(x, y);
+                let f = noise.fractal(x, y, 4, 0.5, 2.0);
+                assert!(n.abs() <= 1.0);
+                assert!(f.abs() <= 1.0);
+                min = min.min(n);
+                max = max.max(n);
+            }
+        }
+        // The noise isn't just flat
+        assert!(min < -0.3);
+        assert!(max > 0.3);
+    }
+
+    #[test]
+    fn test_lattice() {
+        let noise = Perlin::new(0);
+        for x in -5..5 {
+            for y in -5..5 {
+                assert_eq!(noise.get(x as f32, y as f32), 0.0);
+            }
+        }
+    }
+}